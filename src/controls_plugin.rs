@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{direction::{Direction, keypress_to_direction}, Spritesheet, GRID_SIZE, world_plugin::WORLD_SIZE, die_plugin::{get_die_face_sprite_index, Die}};
+use crate::{direction::Direction, key_bindings::KeyBindings, Spritesheet, GRID_SIZE, world_plugin::WORLD_SIZE, die_plugin::{get_die_face_sprite_index, spawn_face_number, Die, FaceLabel}};
 
 pub struct ControlsPlugin;
 
@@ -14,15 +14,17 @@ impl Plugin for ControlsPlugin {
 }
 
 #[derive(Component)]
-struct ControlsDisplay(Direction);
+pub struct ControlsDisplay(pub Direction);
 
 #[derive(Component)]
 struct AdjacentFacesDisplay(Option<Direction>);
 
 fn setup(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
     spritesheet: Res<Spritesheet>,
 ) {
+    let font = asset_server.load("fonts/FreeSans.ttf");
     let display_centre = Vec3::new(-(WORLD_SIZE as f32 / 1.25 * GRID_SIZE), 0.0, 1.0);
 
     for direction in [
@@ -55,6 +57,7 @@ fn setup(
             commands
                 .spawn((
                     AdjacentFacesDisplay(direction),
+                    FaceLabel(0),
                     SpriteSheetBundle{
                         texture_atlas: spritesheet.0.clone(),
                         transform: Transform {
@@ -68,16 +71,18 @@ fn setup(
                         },
                         ..default()
                     }
-                ));
+                ))
+                .with_children(|parent| spawn_face_number(parent, &font));
     }
     
 }
 
 fn show_which_keys_are_pressed(
     keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
     mut control_display_query: Query<(& ControlsDisplay, &mut TextureAtlasSprite)>,
 ) {
-    let key_direction = keypress_to_direction(keyboard_input);
+    let key_direction = key_bindings.direction(&keyboard_input);
 
     for (control_display, mut sprite) in control_display_query.iter_mut() {
         sprite.index = get_direction_key_sprite_index(control_display.0);
@@ -90,22 +95,21 @@ fn show_which_keys_are_pressed(
 
 fn show_which_die_faces_are_adjacent(
     die_query: Query<&Die>,
-    mut adjacent_faces_display_query: Query<(& AdjacentFacesDisplay, &mut TextureAtlasSprite)>,
+    mut adjacent_faces_display_query: Query<(& AdjacentFacesDisplay, &mut TextureAtlasSprite, &mut FaceLabel)>,
 ) {
-    let die = die_query.single();
+    // The HUD follows whichever die the player is rolling
+    let Some(die) = die_query.iter().find(|die| die.active) else { return; };
     
-    for (face_display, mut sprite) in adjacent_faces_display_query.iter_mut() {
-        sprite.index = get_die_face_sprite_index(
-            match face_display.0 {
-                Some(Direction::Up) => die.bottom_number,
-                Some(Direction::Right) => die.left_number,
-                Some(Direction::Down) => die.top_number,
-                Some(Direction::Left) => die.right_number,
-                None => die.face_number,
-            });
+    for (face_display, mut sprite, mut label) in adjacent_faces_display_query.iter_mut() {
+        label.0 = match face_display.0 {
+            Some(direction) => die.orientation.number_facing_after_roll(direction),
+            None => die.orientation.face,
+        };
+        sprite.index = get_die_face_sprite_index(label.0);
     }
 }
 
+#[allow(clippy::needless_return)]
 fn get_direction_key_sprite_index(direction: Direction) -> usize {
     match direction {
         Direction::Up => return 56,
@@ -115,6 +119,7 @@ fn get_direction_key_sprite_index(direction: Direction) -> usize {
     }
 }
 
+#[allow(clippy::needless_return)]
 fn get_direction_key_display_offset(direction: Option<Direction>) -> Vec3 {
     match direction {
         Some(Direction::Up) => return Vec3::new(0.0, -1.0, 0.0),
@@ -0,0 +1,78 @@
+use std::fmt;
+
+const MILLISECONDS_PER_DAY: f64 = 24.0 * 60.0 * 60.0 * 1000.0;
+
+// A calendar date in UTC, so everyone gets the same daily puzzle whatever their time zone
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    pub fn today() -> Date {
+        Date::from_days_since_epoch((milliseconds_since_epoch() / MILLISECONDS_PER_DAY).floor() as i64)
+    }
+
+    // Converts a count of days since 1970-01-01 to a date. See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    pub fn from_days_since_epoch(days: i64) -> Date {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let shifted_month = (5 * day_of_year + 2) / 153; // Counting from March
+        let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+        let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+        let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        Date { year, month, day }
+    }
+
+    // e.g. 2022-07-15 becomes 20220715
+    pub fn seed(&self) -> u64 {
+        (self.year as u64) * 10_000 + (self.month as u64) * 100 + self.day as u64
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn milliseconds_since_epoch() -> f64 {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn milliseconds_since_epoch() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as f64)
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(Date::from_days_since_epoch(0), Date { year: 1970, month: 1, day: 1 });
+        assert_eq!(Date::from_days_since_epoch(19_188), Date { year: 2022, month: 7, day: 15 });
+        assert_eq!(Date::from_days_since_epoch(19_417), Date { year: 2023, month: 3, day: 1 });
+        assert_eq!(Date::from_days_since_epoch(-1), Date { year: 1969, month: 12, day: 31 });
+    }
+
+    #[test]
+    fn each_day_has_its_own_seed() {
+        let today = Date::from_days_since_epoch(20_000);
+        let tomorrow = Date::from_days_since_epoch(20_001);
+        assert_ne!(today.seed(), tomorrow.seed());
+        assert_eq!(Date { year: 2022, month: 7, day: 15 }.seed(), 20220715);
+        assert_eq!(Date { year: 2022, month: 7, day: 15 }.to_string(), "2022-07-15");
+    }
+}
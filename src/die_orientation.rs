@@ -0,0 +1,252 @@
+use serde::Deserialize;
+
+use crate::direction::Direction;
+
+// The highest label a die face can have. 0 is a blank face, 1 to `MAX_PIPS` are shown with pips, and anything higher is
+// printed on the face as a number.
+pub const MAX_FACE_LABEL: usize = 99;
+pub const MAX_PIPS: usize = 6;
+
+// Which number is showing on each side of the die. Dice start as real ones, but paint tiles can relabel their sides.
+// `face` points at the camera, `hidden` is against the floor, and top/right/bottom/left are the sides as seen on screen.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Deserialize)]
+pub struct DieOrientation {
+    pub face: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub hidden: usize,
+}
+
+impl Default for DieOrientation {
+    fn default() -> Self {
+        DieOrientation {
+            face: 1,
+            top: 2,
+            right: 3,
+            bottom: 5,
+            left: 4,
+            hidden: 6,
+        }
+    }
+}
+
+impl DieOrientation {
+    // The orientation after tipping the die over one tile in `direction`
+    #[allow(clippy::needless_return)]
+    pub fn roll(&self, direction: Direction) -> DieOrientation {
+        let mut rolled = *self;
+        match direction {
+            Direction::Up => {
+                rolled.face = self.bottom;
+                rolled.bottom = self.hidden;
+                rolled.hidden = self.top;
+                rolled.top = self.face;
+            },
+            Direction::Right => {
+                rolled.face = self.left;
+                rolled.left = self.hidden;
+                rolled.hidden = self.right;
+                rolled.right = self.face;
+            },
+            Direction::Down => {
+                rolled.face = self.top;
+                rolled.top = self.hidden;
+                rolled.hidden = self.bottom;
+                rolled.bottom = self.face;
+            },
+            Direction::Left => {
+                rolled.face = self.right;
+                rolled.right = self.hidden;
+                rolled.hidden = self.left;
+                rolled.left = self.face;
+            },
+        };
+        return rolled;
+    }
+
+    // The orientation after turning the die a quarter turn on the spot, as seen on screen.
+    // The face and hidden numbers stay put while the four sides move round.
+    #[allow(clippy::needless_return)]
+    pub fn spin(&self, clockwise: bool) -> DieOrientation {
+        let mut spun = *self;
+        if clockwise {
+            spun.right = self.top;
+            spun.bottom = self.right;
+            spun.left = self.bottom;
+            spun.top = self.left;
+        }
+        else {
+            spun.left = self.top;
+            spun.bottom = self.left;
+            spun.right = self.bottom;
+            spun.top = self.right;
+        }
+        return spun;
+    }
+
+    // The same die with the side against the floor relabelled
+    pub fn paint(&self, label: usize) -> DieOrientation {
+        DieOrientation { hidden: label, ..*self }
+    }
+
+    // The orientation the die had before it rolled in `direction` to reach this one. Undo can't use it, as tiles like
+    // rotators and paint can change the die after the roll, so it keeps the orientation from before instead.
+    #[cfg(test)]
+    pub fn unroll(&self, direction: Direction) -> DieOrientation {
+        self.roll(direction.opposite())
+    }
+
+    // The number that would be face up after rolling in `direction`
+    #[allow(clippy::needless_return)]
+    pub fn number_facing_after_roll(&self, direction: Direction) -> usize {
+        match direction {
+            Direction::Up => return self.bottom,
+            Direction::Right => return self.left,
+            Direction::Down => return self.top,
+            Direction::Left => return self.right,
+        }
+    }
+
+    // A real die uses each of 1-6 once, with opposite sides adding up to 7
+    #[allow(clippy::needless_return)]
+    pub fn is_valid(&self) -> bool {
+        let mut numbers = self.numbers();
+        numbers.sort();
+
+        return numbers == [1, 2, 3, 4, 5, 6] &&
+            self.face + self.hidden == 7 &&
+            self.top + self.bottom == 7 &&
+            self.left + self.right == 7;
+    }
+
+    fn numbers(&self) -> [usize; 6] {
+        [self.face, self.top, self.right, self.bottom, self.left, self.hidden]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+    // Every orientation the die can be rolled into from the default one
+    #[allow(clippy::needless_return)]
+    fn all_orientations() -> HashSet<DieOrientation> {
+        let mut found = HashSet::from([DieOrientation::default()]);
+        let mut to_visit = vec![DieOrientation::default()];
+        while let Some(orientation) = to_visit.pop() {
+            for direction in DIRECTIONS {
+                let rolled = orientation.roll(direction);
+                if found.insert(rolled) {
+                    to_visit.push(rolled);
+                }
+            }
+        }
+        return found;
+    }
+
+    #[test]
+    fn default_is_valid() {
+        assert!(DieOrientation::default().is_valid());
+    }
+
+    #[test]
+    fn rolling_brings_the_opposite_side_face_up() {
+        let start = DieOrientation::default();
+        assert_eq!(start.roll(Direction::Up).face, start.bottom);
+        assert_eq!(start.roll(Direction::Down).face, start.top);
+        assert_eq!(start.roll(Direction::Left).face, start.right);
+        assert_eq!(start.roll(Direction::Right).face, start.left);
+    }
+
+    #[test]
+    fn rolling_up_moves_every_side() {
+        let rolled = DieOrientation::default().roll(Direction::Up);
+        assert_eq!(rolled, DieOrientation { face: 5, top: 1, right: 3, bottom: 6, left: 4, hidden: 2 });
+    }
+
+    #[test]
+    fn number_facing_after_roll_matches_roll() {
+        for orientation in all_orientations() {
+            for direction in DIRECTIONS {
+                assert_eq!(orientation.number_facing_after_roll(direction), orientation.roll(direction).face);
+            }
+        }
+    }
+
+    #[test]
+    fn rolling_keeps_the_die_valid() {
+        for orientation in all_orientations() {
+            assert!(orientation.is_valid(), "{:?}", orientation);
+        }
+    }
+
+    #[test]
+    fn every_rotation_of_a_cube_is_reachable() {
+        assert_eq!(all_orientations().len(), 24);
+    }
+
+    #[test]
+    fn four_rolls_in_one_direction_return_to_the_start() {
+        for orientation in all_orientations() {
+            for direction in DIRECTIONS {
+                let mut rolled = orientation;
+                for _ in 0..4 {
+                    rolled = rolled.roll(direction);
+                }
+                assert_eq!(rolled, orientation, "rolling {:?}", direction);
+            }
+        }
+    }
+
+    #[test]
+    fn unroll_undoes_roll() {
+        for orientation in all_orientations() {
+            for direction in DIRECTIONS {
+                assert_eq!(orientation.roll(direction).unroll(direction), orientation);
+                assert_eq!(orientation.unroll(direction).roll(direction), orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn spinning_keeps_the_face_and_hidden_numbers() {
+        for orientation in all_orientations() {
+            for clockwise in [true, false] {
+                let spun = orientation.spin(clockwise);
+                assert_eq!((spun.face, spun.hidden), (orientation.face, orientation.hidden));
+                assert!(spun.is_valid());
+                assert!(all_orientations().contains(&spun));
+            }
+            assert_eq!(orientation.spin(true).spin(false), orientation);
+        }
+
+        let spun = DieOrientation::default().spin(true);
+        assert_eq!(spun, DieOrientation { face: 1, top: 4, right: 2, bottom: 3, left: 5, hidden: 6 });
+    }
+
+    #[test]
+    fn painted_labels_roll_round_with_the_die() {
+        let painted = DieOrientation::default().paint(3);
+        assert_eq!(painted, DieOrientation { face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 3 });
+        assert!(!painted.is_valid());
+
+        let rolled = painted.roll(Direction::Up).roll(Direction::Up);
+        assert_eq!(rolled.face, 3);
+        assert_eq!(rolled.roll(Direction::Left).face, 3);
+    }
+
+    #[test]
+    fn invalid_dice_are_rejected() {
+        let repeated_number = DieOrientation { face: 1, top: 1, right: 3, bottom: 5, left: 4, hidden: 6 };
+        assert!(!repeated_number.is_valid());
+
+        let wrong_opposites = DieOrientation { face: 1, top: 2, right: 3, bottom: 4, left: 5, hidden: 6 };
+        assert!(!wrong_opposites.is_valid());
+    }
+}
@@ -2,7 +2,7 @@ use std::ops::Sub;
 
 use bevy::prelude::*;
 
-use crate::{Collider, GRID_SIZE, PressurePlate, Spritesheet, GameState, PIXEL_SCALE};
+use crate::{Collider, GRID_SIZE, PressurePlate, Spritesheet, GameState, PIXEL_SCALE, die_orientation::{DieOrientation, MAX_PIPS}, key_bindings::{Action, KeyBindings}, level::{DieStart, PlateMode}, world_plugin::{Conveyor, Crate, Crumbling, Filter, Ice, LevelSpawned, LevelStats, Paint, Pit, PlateOrder, Rotator, Switch, Teleporter, TileColour, TELEPORTER_SPRITE_INDEX}};
 
 use super::direction::{
     *,
@@ -14,28 +14,44 @@ pub struct DiePlugin;
 impl Plugin for DiePlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(PostStartup, setup)
+            .init_resource::<MoveHistory>()
+            .add_systems(Update, (clear_move_history, tick_teleport_flashes, show_active_die))
+            .add_systems(Update, (update_die_face_labels, show_face_numbers).chain())
+            .add_systems(Update, switch_active_die.run_if(in_state(GameState::Playing)))
+            .add_systems(
+                FixedUpdate,
+                (
+                    undo_move,
+                    redo_move,
+                ).chain().before(react_to_input).run_if(in_state(GameState::Playing))
+            )
             .add_systems(
                 FixedUpdate,
                 (
                     react_to_input,
                     tick_animation,
                     tick_motion,
-                    new_check_pressure_plates
-                ).run_if(in_state(GameState::Playing))
+                    tick_crate_motion,
+                    new_check_pressure_plates,
+                    react_to_tile,
+                    tick_falling,
+                ).chain().run_if(in_state(GameState::Playing))
             );
     }
 }
 
-pub const DIE_STARTING_POSITION: Vec3 = Vec3::from_array([0.0, 0.0, 1.0]);
+const DIE_HEIGHT: f32 = 1.0;
 const DIE_SPEED: f32 = PIXEL_SCALE * GRID_SIZE * 2.0; // SMaller is faster. Dunno why
-
-fn setup(
-    mut commands: Commands,
-    spritesheet: Res<Spritesheet>,
-) {
-    commands.spawn(DieBundle::new(&spritesheet.0));
-}
+const INACTIVE_DIE_COLOUR: Color = Color::rgb(0.6, 0.6, 0.65);
+pub const PLAYER_COLOURS: [Color; 2] = [Color::rgb(1.0, 0.55, 0.55), Color::rgb(0.55, 0.75, 1.0)];
+const TELEPORT_FLASH_SECONDS: f32 = 0.3;
+const FALL_SECONDS: f32 = 0.6;
+const MAX_CARRIED_ROLLS: usize = 256;
+const FACE_NUMBER_FONT_SIZE: f32 = 32.0;
+const FACE_NUMBER_COLOUR: Color = Color::rgb(0.1, 0.1, 0.1);
+// Column 0 of the die's rows is the blank face, plain, lit and held down
+const BLANK_FACE_SPRITE_INDICES: [usize; 3] = [0, 7, 14];
+const SPIN_SPEED: f32 = std::f32::consts::FRAC_PI_2 / 0.25; // Radians per second, a quarter turn takes a quarter of a second
 
 #[derive(PartialEq)]
 enum DieAnimation {
@@ -43,52 +59,85 @@ enum DieAnimation {
     Frame1,
     Frame2,
     Frame3,
+    Sliding, // Moving without rolling, so the same face stays up the whole way
+    Spinning { clockwise: bool }, // Turning on the spot
+    Falling, // Dropping into a pit
 }
 
 #[derive(Component)]
 pub struct Die {
-    pub face_number: usize,
-    pub top_number: usize,
-    pub right_number: usize,
-    pub bottom_number: usize,
-    pub left_number: usize,
-    pub hidden_number: usize,
+    pub orientation: DieOrientation,
     animation_state: DieAnimation,
     pub animation_direction: Direction,
     pub destination_translation: Vec3, // Store tile index, NOT absolute position
+    trigger_tile_on_landing: bool, // Whether the tile the die is heading for gets to act on it, e.g. ice making it slide
+    carried_rolls: usize, // Rolls conveyors have made it since the player last rolled it
+    pub index: usize, // Order the dice are switched between in
+    pub active: bool, // Whether this is the die the player is rolling
+    pub player: Option<usize>, // Who rolls it in two-player mode, 0 for player one
+}
+
+// Every roll made on the current level, so they can be taken back and replayed
+#[derive(Resource, Default)]
+struct MoveHistory {
+    undo: Vec<Move>,
+    redo: Vec<Move>,
 }
 
+struct Move {
+    die: Entity,
+    direction: Direction,
+    orientation_before: DieOrientation,
+    destination_before: Vec3,
+    destination_after: Vec3,
+    plates_before: Vec<(Entity, PressurePlate)>, // Each pressure plate as it was before the roll
+    switches_before: Vec<(Entity, bool)>, // Whether each switch was on before the roll
+    plate_order_before: usize, // `PlateOrder::next` before the roll
+    crumbling_before: Vec<(Entity, Crumbling)>, // Each crumbling floor as it was before the roll
+    crates_before: Vec<(Entity, Vec3)>, // Where each crate was before the roll
+}
+
+// The label on the die face a sprite is showing, so labels past `MAX_PIPS` can be printed over it
+#[derive(Component)]
+pub struct FaceLabel(pub usize);
+
+// Text showing the parent's `FaceLabel`, when it has no pips to show it with
+#[derive(Component)]
+struct FaceNumber;
+
 #[derive(Bundle)]
 struct DieBundle {
     die: Die,
+    label: FaceLabel,
     collider: Collider,
     sprite_bundle: SpriteSheetBundle,
 }
 
 impl DieBundle {
-    fn new(texture_atlas_handle: &Handle<TextureAtlas>) -> DieBundle {
+    fn new(texture_atlas_handle: &Handle<TextureAtlas>, grid_position: Vec2, orientation: DieOrientation, index: usize, player: Option<usize>) -> DieBundle {
         DieBundle { 
             die: Die { 
-                face_number: 1,
-                top_number: 2,
-                right_number: 3,
-                bottom_number: 5,
-                left_number: 4,
-                hidden_number: 6,
+                orientation,
                 animation_state: DieAnimation::None,
                 animation_direction: Direction::Up,
-                destination_translation: DIE_STARTING_POSITION / GRID_SIZE,
+                destination_translation: die_grid_translation(grid_position),
+                trigger_tile_on_landing: false,
+                carried_rolls: 0,
+                index,
+                active: index == 0,
+                player,
             },
+            label: FaceLabel(orientation.face),
             collider: Collider,
             sprite_bundle: SpriteSheetBundle {
                 texture_atlas: texture_atlas_handle.clone(),
                 transform: Transform {
-                    translation: DIE_STARTING_POSITION,
+                    translation: die_grid_translation(grid_position) * GRID_SIZE,
                     scale: Vec3::splat(super::PIXEL_SCALE),
                     ..default()
                 },
                 sprite: TextureAtlasSprite {
-                    index: get_die_face_sprite_index(1),
+                    index: get_die_face_sprite_index(orientation.face),
                     ..default()
                 },
                 ..default()
@@ -97,70 +146,425 @@ impl DieBundle {
     }
 }
 
-fn react_to_input(
+// Where the die sits when on the given tile, in the grid units used by `Die::destination_translation`
+pub fn die_grid_translation(grid_position: Vec2) -> Vec3 {
+    grid_position.extend(DIE_HEIGHT / GRID_SIZE)
+}
+
+// The first die spawned is the one the player starts off rolling
+pub fn spawn_die(commands: &mut Commands, texture_atlas_handle: &Handle<TextureAtlas>, font: &Handle<Font>, grid_position: Vec2, start: &DieStart, index: usize, player: Option<usize>) {
+    commands
+        .spawn(DieBundle::new(texture_atlas_handle, grid_position, start.orientation, index, player))
+        .with_children(|parent| spawn_face_number(parent, font));
+}
+
+// Goes on anything with a `FaceLabel`. It's hidden until the label is one without pips.
+pub fn spawn_face_number(parent: &mut ChildBuilder, font: &Handle<Font>) {
+    parent.spawn((
+        FaceNumber,
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: FACE_NUMBER_FONT_SIZE,
+                    color: FACE_NUMBER_COLOUR,
+                },
+            ),
+            // Undo the parent's scale, so the text isn't blown up like the pixel art
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, 0.1),
+                scale: Vec3::splat(1.0 / PIXEL_SCALE),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+fn update_die_face_labels(
+    mut die_query: Query<(&Die, &mut FaceLabel)>,
+) {
+    for (die, mut label) in die_query.iter_mut() {
+        if label.0 != die.orientation.face {
+            label.0 = die.orientation.face;
+        }
+    }
+}
+
+// Mid roll the sprite shows the die tipping over rather than a face, so the number is only shown on the blank face
+fn show_face_numbers(
+    labelled_query: Query<(&FaceLabel, &TextureAtlasSprite)>,
+    mut numbers_query: Query<(&Parent, &mut Text, &mut Visibility), With<FaceNumber>>,
+) {
+    for (parent, mut text, mut visibility) in numbers_query.iter_mut() {
+        let Ok((label, sprite)) = labelled_query.get(parent.get()) else { continue; };
+        let shown = label.0 > MAX_PIPS && BLANK_FACE_SPRITE_INDICES.contains(&sprite.index);
+        *visibility = if shown { Visibility::Inherited } else { Visibility::Hidden };
+        if shown && text.sections[0].value != label.0.to_string() {
+            text.sections[0].value = label.0.to_string();
+        }
+    }
+}
+
+// Whether every die has come to rest, so the player can do something else
+pub fn all_dice_at_rest<'a>(mut dice: impl Iterator<Item = &'a Die>) -> bool {
+    dice.all(|die| die.animation_state == DieAnimation::None)
+}
+
+// In two-player mode each player has their own die, and nobody gets to swap dice or take rolls back
+fn is_race<'a>(mut dice: impl Iterator<Item = &'a Die>) -> bool {
+    dice.any(|die| die.player.is_some())
+}
+
+fn switch_active_die(
     keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
     mut die_query: Query<&mut Die>,
-    colliders_query: Query<
-        & Transform,
-        (With<Collider>,Without<Die>),
-    >
 ) {
-    let mut die = die_query.single_mut();
-    if die.animation_state != DieAnimation::None { return; }
-    
-    let direction = keypress_to_direction(keyboard_input);
-    if direction.is_none() { return; }
-    let direction = direction.unwrap();
-
-    let new_position = die.destination_translation + translation_from_direction(&direction);
-
-    for collider in colliders_query.iter() {
-        if is_colliding(new_position * GRID_SIZE, collider.translation) {
-            return;
+    if !key_bindings.just_pressed(&keyboard_input, Action::SwitchDie) || !all_dice_at_rest(die_query.iter()) || is_race(die_query.iter()) { return; }
+    let Some(active) = die_query.iter().find(|die| die.active).map(|die| die.index) else { return; };
+
+    let next = (active + 1) % die_query.iter().count();
+    for mut die in die_query.iter_mut() {
+        die.active = die.index == next;
+    }
+}
+
+// Dice waiting their turn are greyed out, and players' dice are tinted their colour
+fn show_active_die(
+    mut die_query: Query<(&Die, &mut TextureAtlasSprite)>,
+) {
+    for (die, mut sprite) in die_query.iter_mut() {
+        if die.animation_state == DieAnimation::Falling { continue; }
+        sprite.color = match die.player {
+            Some(player) => PLAYER_COLOURS[player],
+            None if die.active => Color::WHITE,
+            None => INACTIVE_DIE_COLOUR,
+        };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn react_to_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut history: ResMut<MoveHistory>,
+    mut level_stats: ResMut<LevelStats>,
+    mut die_query: Query<(Entity, &mut Die)>,
+    colliders_query: CollidersQuery,
+    filters_query: Query<(&Transform, &Filter), Without<Die>>,
+    pits_query: Query<&Transform, (With<Pit>, Without<Die>)>,
+    pressure_plates_query: Query<(Entity, &PressurePlate)>,
+    switches_query: Query<(Entity, &Switch)>,
+    plate_order: Res<PlateOrder>,
+    crumbling_query: Query<(Entity, &Crumbling)>,
+    mut crates_query: Query<(Entity, &mut Crate)>,
+) {
+    // In a race each player rolls their own die whenever it's stopped, otherwise the active die rolls once they all have
+    let racing = is_race(die_query.iter().map(|(_, die)| die));
+    let rolls: Vec<(Entity, Direction)> = if racing {
+        die_query
+            .iter()
+            .filter(|(_, die)| die.animation_state == DieAnimation::None)
+            .filter_map(|(entity, die)| {
+                let direction = match die.player? {
+                    0 => key_bindings.direction(&keyboard_input),
+                    _ => key_bindings.player_two_keys().and_then(|keys| keys_to_direction(&keyboard_input, keys)),
+                };
+                Some((entity, direction?))
+            })
+            .collect()
+    } else {
+        if !all_dice_at_rest(die_query.iter().map(|(_, die)| die)) { return; }
+
+        let direction = key_bindings.direction(&keyboard_input);
+        if direction.is_none() { return; }
+        let direction = direction.unwrap();
+
+        die_query.iter().filter(|(_, die)| die.active).map(|(entity, _)| (entity, direction)).collect()
+    };
+
+    for (die_entity, direction) in rolls {
+        let other_dice: Vec<Vec3> = die_query
+            .iter()
+            .filter(|(entity, _)| *entity != die_entity)
+            .map(|(_, die)| die.destination_translation)
+            .collect();
+        let Ok((_, mut die)) = die_query.get_mut(die_entity) else { continue; };
+
+        let new_position = die.destination_translation + translation_from_direction(&direction);
+        let face_after_roll = die.orientation.number_facing_after_roll(direction);
+
+        // Rolling into a crate pushes it along one tile, as long as there's room for it there
+        let crate_ahead = crates_query
+            .iter()
+            .find(|(_, pushed)| is_colliding(pushed.destination * GRID_SIZE, new_position * GRID_SIZE))
+            .map(|(entity, _)| entity);
+        if is_blocked(new_position, face_after_roll, crate_ahead, &colliders_query, &filters_query, &other_dice) { continue; }
+        if crate_ahead.is_some() {
+            let pushed_to = (new_position + translation_from_direction(&direction)) * GRID_SIZE;
+            let crate_blocked = colliders_query.iter().any(|(_, collider)| is_colliding(pushed_to, collider.translation)) ||
+                pits_query.iter().any(|pit| is_colliding(pushed_to, pit.translation)) ||
+                other_dice.iter().any(|other_die| is_colliding(pushed_to, *other_die * GRID_SIZE));
+            if crate_blocked { continue; }
+        }
+
+        let orientation_before = die.orientation;
+        let destination_before = die.destination_translation;
+        let crates_before = crates_query
+            .iter()
+            .map(|(entity, pushed)| (entity, pushed.destination))
+            .collect();
+
+        push_crate(&die, direction, &mut crates_query);
+        start_roll(&mut die, direction);
+        die.carried_rolls = 0;
+
+        // Races can't be taken back, and both players' rolls in the one move count would mean nothing
+        if racing { continue; }
+        history.undo.push(Move {
+            die: die_entity,
+            direction,
+            orientation_before,
+            destination_before,
+            destination_after: die.destination_translation,
+            plates_before: pressure_plates_query
+                .iter()
+                .map(|(entity, pressure_plate)| (entity, *pressure_plate))
+                .collect(),
+            switches_before: switches_query
+                .iter()
+                .map(|(entity, switch)| (entity, switch.on))
+                .collect(),
+            plate_order_before: plate_order.next,
+            crumbling_before: crumbling_query
+                .iter()
+                .map(|(entity, crumbling)| (entity, *crumbling))
+                .collect(),
+            crates_before,
+        });
+        history.redo.clear();
+        level_stats.moves += 1;
+    }
+}
+
+// Moves the crate the die is about to roll into, if there is one. The caller checks it has room.
+fn push_crate(die: &Die, direction: Direction, crates_query: &mut Query<(Entity, &mut Crate)>) {
+    let ahead = die.destination_translation + translation_from_direction(&direction);
+    for (_, mut pushed) in crates_query.iter_mut() {
+        if is_colliding(pushed.destination * GRID_SIZE, ahead * GRID_SIZE) {
+            pushed.destination += translation_from_direction(&direction);
         }
     }
+}
 
-    die.destination_translation = new_position;
-    rotate_die(&mut die, &direction);
+// Tips the die over onto the next tile in `direction`. The caller checks nothing is in the way.
+fn start_roll(die: &mut Die, direction: Direction) {
+    die.destination_translation += translation_from_direction(&direction);
+    die.orientation = die.orientation.roll(direction);
 
     die.animation_state = DieAnimation::Frame1;
-    die.animation_direction = direction.clone();
+    die.animation_direction = direction;
+    die.trigger_tile_on_landing = true;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn undo_move(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut history: ResMut<MoveHistory>,
+    mut level_stats: ResMut<LevelStats>,
+    mut die_query: Query<(Entity, &mut Die, &mut Transform)>,
+    mut pressure_plates_query: Query<&mut PressurePlate>,
+    mut switches_query: Query<&mut Switch>,
+    mut plate_order: ResMut<PlateOrder>,
+    mut crumbling_query: Query<&mut Crumbling>,
+    mut crates_query: Query<(&mut Crate, &mut Transform), Without<Die>>,
+) {
+    if !all_dice_at_rest(die_query.iter().map(|(_, die, _)| die)) || !key_bindings.pressed(&keyboard_input, Action::Undo) { return; }
+    if is_race(die_query.iter().map(|(_, die, _)| die)) { return; }
+    let Some(last_move) = history.undo.pop() else { return; };
+
+    // Undoing another die's roll switches back to that die
+    for (entity, mut die, _) in die_query.iter_mut() {
+        die.active = entity == last_move.die;
+    }
+    let Ok((_, mut die, mut die_transform)) = die_query.get_mut(last_move.die) else { return; };
+
+    // If a tile carried the die on after the roll, jump back to where the roll landed first
+    die_transform.translation = last_move.destination_after * GRID_SIZE;
+    die.orientation = last_move.orientation_before;
+    die.destination_translation = last_move.destination_before;
+
+    // Roll back the way it came, so the animation plays in reverse
+    die.animation_state = DieAnimation::Frame1;
+    die.animation_direction = last_move.direction.opposite();
+    die.trigger_tile_on_landing = false; // Otherwise undoing back onto ice would slide off again
+
+    for (entity, before) in last_move.plates_before.iter() {
+        if let Ok(mut pressure_plate) = pressure_plates_query.get_mut(*entity) {
+            *pressure_plate = *before;
+        }
+    }
+    for (entity, on) in last_move.switches_before.iter() {
+        if let Ok(mut switch) = switches_query.get_mut(*entity) {
+            switch.on = *on;
+        }
+    }
+    plate_order.next = last_move.plate_order_before;
+    for (entity, before) in last_move.crumbling_before.iter() {
+        if let Ok(mut crumbling) = crumbling_query.get_mut(*entity) {
+            *crumbling = *before;
+        }
+    }
+    // Crates jump straight back, otherwise they'd hold the plates they were pushed onto down again on the way
+    for (entity, destination) in last_move.crates_before.iter() {
+        if let Ok((mut pushed, mut transform)) = crates_query.get_mut(*entity) {
+            pushed.destination = *destination;
+            transform.translation = *destination * GRID_SIZE;
+        }
+    }
+
+    history.redo.push(last_move);
+    level_stats.moves = level_stats.moves.saturating_sub(1);
+}
+
+fn redo_move(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut history: ResMut<MoveHistory>,
+    mut level_stats: ResMut<LevelStats>,
+    mut die_query: Query<(Entity, &mut Die)>,
+    mut crates_query: Query<(Entity, &mut Crate)>,
+) {
+    if !all_dice_at_rest(die_query.iter().map(|(_, die)| die)) || !key_bindings.pressed(&keyboard_input, Action::Redo) { return; }
+    if is_race(die_query.iter().map(|(_, die)| die)) { return; }
+    let Some(next_move) = history.redo.pop() else { return; };
+
+    for (entity, mut die) in die_query.iter_mut() {
+        die.active = entity == next_move.die;
+    }
+    let Ok((_, mut die)) = die_query.get_mut(next_move.die) else { return; };
+
+    // Plates are re-activated by `new_check_pressure_plates` when the die lands
+    push_crate(&die, next_move.direction, &mut crates_query);
+    start_roll(&mut die, next_move.direction);
+    die.carried_rolls = 0;
+
+    history.undo.push(next_move);
+    level_stats.moves += 1;
+}
+
+fn clear_move_history(
+    mut level_spawned_events: EventReader<LevelSpawned>,
+    mut history: ResMut<MoveHistory>,
+) {
+    if level_spawned_events.iter().count() == 0 { return; }
+
+    history.undo.clear();
+    history.redo.clear();
 }
 
 fn new_check_pressure_plates(
+    mut plate_order: ResMut<PlateOrder>,
     mut die_query: Query<(&Transform, &Die, &mut TextureAtlasSprite)>,
-    mut pressure_plates_query: Query<(&mut PressurePlate, &Transform)>
+    mut pressure_plates_query: Query<(&mut PressurePlate, &Transform)>,
+    crates_query: Query<&Transform, With<Crate>>,
 ) {
-    let (die_transform, die, mut sprite) = die_query.single_mut();
+    let mut out_of_order = false;
     for (mut pressure_plate, pp_transform) in pressure_plates_query.iter_mut() {
-        if is_colliding(die_transform.translation, pp_transform.translation) &&
-        (die.animation_state == DieAnimation::None ||  die.animation_state == DieAnimation::Frame3) {
-            if die.face_number == pressure_plate.number {
-                pressure_plate.activated = true;
-                if die.animation_state == DieAnimation::None { sprite.index = get_die_face_sprite_index(die.face_number) + 14};
+        // The player whose die is pressing the plate, if it's in a race
+        let pressed_by = die_query
+            .iter()
+            .find(|(die_transform, die, _)|
+                is_colliding(die_transform.translation, pp_transform.translation) &&
+                (die.animation_state == DieAnimation::None ||  die.animation_state == DieAnimation::Frame3) &&
+                die.orientation.face == pressure_plate.number)
+            .map(|(_, die, _)| die.player);
+        let crate_on_plate = crates_query.iter().any(|crate_transform| is_colliding(crate_transform.translation, pp_transform.translation));
+        // Crates hold plates down whatever their number
+        let pressed = crate_on_plate || pressed_by.is_some();
+        let in_turn = pressure_plate.completed ||
+            pressure_plate.order_step.map_or(true, |step| step == plate_order.next);
+        if pressed && in_turn {
+            if !pressure_plate.completed && pressure_plate.order_step.is_some() {
+                plate_order.next += 1;
+            }
+            if !pressure_plate.completed {
+                pressure_plate.owner = pressed_by.flatten();
+            }
+            pressure_plate.activated = true;
+            pressure_plate.completed = true;
+        }
+        else {
+            if pressed {
+                out_of_order = true;
             }
-            else {
-                if die.animation_state == DieAnimation::None { sprite.index = get_die_face_sprite_index(die.face_number) + 7};
+            // Only latching plates stay down once the die leaves or the wrong number lands on them
+            if pressure_plate.mode != PlateMode::Latching && pressure_plate.activated {
+                pressure_plate.activated = false;
+            }
+        }
+
+        // Light up each die resting on the plate, brighter if it's the one holding it down
+        for (die_transform, die, mut sprite) in die_query.iter_mut() {
+            if die.animation_state != DieAnimation::None || !is_colliding(die_transform.translation, pp_transform.translation) { continue; }
+            let holding_down = pressed && in_turn && die.orientation.face == pressure_plate.number;
+            sprite.index = get_die_face_sprite_index(die.orientation.face) + if holding_down { 14 } else { 7 };
+        }
+    }
+
+    // Activating a plate out of turn starts the order again
+    if out_of_order && plate_order.next > 0 {
+        plate_order.next = 0;
+        for (mut pressure_plate, _) in pressure_plates_query.iter_mut() {
+            if pressure_plate.order_step.is_some() {
+                pressure_plate.activated = false;
+                pressure_plate.completed = false;
+                pressure_plate.owner = None;
             }
         }
     }
 }
 
 fn tick_animation(
-    mut die_query: Query<(& Transform, &mut TextureAtlasSprite, &mut Die)>,
+    time: Res<Time>,
+    mut die_query: Query<(&mut Transform, &mut TextureAtlasSprite, &mut Die)>,
 ) {
-    let (
-        transform,
-        mut sprite,
-        mut die) = die_query.single_mut();
+    for (mut transform, mut sprite, mut die) in die_query.iter_mut() {
+        animate_die(time.delta_seconds(), &mut transform, &mut sprite, &mut die);
+    }
+}
+
+fn animate_die(delta_seconds: f32, transform: &mut Transform, sprite: &mut TextureAtlasSprite, die: &mut Die) {
     if die.animation_state == DieAnimation::None { return; }
 
+    if let DieAnimation::Spinning { clockwise } = die.animation_state {
+        // The orientation has already been spun, so just turn the sprite until it's gone a quarter turn then put it back straight
+        let turn = if clockwise { -SPIN_SPEED } else { SPIN_SPEED } * delta_seconds;
+        transform.rotate_z(turn);
+        if transform.rotation.angle_between(Quat::IDENTITY) >= std::f32::consts::FRAC_PI_2 {
+            transform.rotation = Quat::IDENTITY;
+            die.animation_state = DieAnimation::None;
+        }
+        sprite.index = get_die_face_sprite_index(die.orientation.face);
+        return;
+    }
+
     let dist_to_dest = die.destination_translation * GRID_SIZE - transform.translation;
 
+    if die.animation_state == DieAnimation::Sliding {
+        if dist_to_dest.length() < 4.0 { die.animation_state = DieAnimation::None; }
+        sprite.index = get_die_face_sprite_index(die.orientation.face);
+        return;
+    }
+
     match dist_to_dest.length().abs().round() as i32{
         40.. => {
             die.animation_state = DieAnimation::Frame1;
-            sprite.index = get_die_face_sprite_index(die.face_number);
+            sprite.index = get_die_face_sprite_index(die.orientation.face);
         },
         28..=39 => {
             die.animation_state = DieAnimation::Frame1;
@@ -176,7 +580,7 @@ fn tick_animation(
         },
         _ => {
             die.animation_state = DieAnimation::None;
-            sprite.index = get_die_face_sprite_index(die.face_number);
+            sprite.index = get_die_face_sprite_index(die.orientation.face);
         },
 
     }
@@ -186,22 +590,238 @@ fn tick_motion(
     time: Res<Time>,
     mut die_query: Query<(&mut Transform, &Die)>,
 ) {
-    let (mut die_transform, die) = die_query.single_mut();
+    for (mut die_transform, die) in die_query.iter_mut() {
+        let diff = (die.destination_translation * GRID_SIZE) - die_transform.translation;
+        if diff == Vec3::ZERO { continue; } // Already there, e.g. while spinning on the spot
+
+        die_transform.translation += velocity_towards(diff, time.delta().as_secs_f32());
+        if die.animation_state == DieAnimation::None { 
+            die_transform.translation = die.destination_translation * GRID_SIZE;
+        }
+    }
+}
 
-    let diff = (die.destination_translation * GRID_SIZE) - die_transform.translation;
+// Crates move at the same speed as the die, so a pushed crate gets there when the die does
+fn tick_crate_motion(
+    time: Res<Time>,
+    mut crates_query: Query<(&mut Transform, &Crate), Without<Die>>,
+) {
+    for (mut transform, pushed) in crates_query.iter_mut() {
+        let diff = (pushed.destination * GRID_SIZE) - transform.translation;
+        if diff == Vec3::ZERO { continue; }
+
+        transform.translation += velocity_towards(diff, time.delta().as_secs_f32());
+    }
+}
+
+// How far to move this tick to head for something `diff` away
+#[allow(clippy::needless_return)]
+fn velocity_towards(diff: Vec3, delta_seconds: f32) -> Vec3 {
     let travel_direction = diff/diff.length().abs();
 
-    let mut velocity = (travel_direction * GRID_SIZE) / (DIE_SPEED * time.delta().as_secs_f32());
+    let mut velocity = (travel_direction * GRID_SIZE) / (DIE_SPEED * delta_seconds);
     if velocity.length() > diff.length() {
         velocity = velocity * diff.length()/velocity.length(); // Limit velocity so you cannot overshoot
     }
-    die_transform.translation += velocity;
-    if die.animation_state == DieAnimation::None { 
-        die_transform.translation = die.destination_translation * GRID_SIZE;
+    return velocity;
+}
+
+// Runs once the die comes to rest on a tile, letting that tile move it on
+#[allow(clippy::too_many_arguments)]
+fn react_to_tile(
+    mut commands: Commands,
+    spritesheet: Res<Spritesheet>,
+    mut die_query: Query<(Entity, &mut Die, &mut Transform)>,
+    ice_query: Query<&Transform, (With<Ice>, Without<Die>)>,
+    teleporters_query: Query<(&Transform, &Teleporter, &TileColour), Without<Die>>,
+    conveyors_query: Query<(&Transform, &Conveyor), Without<Die>>,
+    rotators_query: Query<(&Transform, &Rotator), Without<Die>>,
+    mut switches_query: Query<(&Transform, &mut Switch), Without<Die>>,
+    paints_query: Query<(&Transform, &Paint), Without<Die>>,
+    pits_query: Query<&Transform, (With<Pit>, Without<Die>)>,
+    colliders_query: CollidersQuery,
+    filters_query: Query<(&Transform, &Filter), Without<Die>>,
+) {
+    // Dice can land on the same tick, e.g. both players' in a race
+    let landed: Vec<Entity> = die_query
+        .iter()
+        .filter(|(_, die, _)| die.animation_state == DieAnimation::None && die.trigger_tile_on_landing)
+        .map(|(entity, _, _)| entity)
+        .collect();
+    for die_entity in landed {
+        let other_dice: Vec<Vec3> = die_query
+            .iter()
+            .filter(|(entity, _, _)| *entity != die_entity)
+            .map(|(_, die, _)| die.destination_translation)
+            .collect();
+        let Ok((_, mut die, mut die_transform)) = die_query.get_mut(die_entity) else { continue; };
+        die.trigger_tile_on_landing = false;
+
+        if pits_query.iter().any(|pit| is_colliding(die.destination_translation * GRID_SIZE, pit.translation)) {
+            die.animation_state = DieAnimation::Falling;
+            continue;
+        }
+
+        let is_ice = |grid_position: Vec3| ice_query.iter().any(|ice| is_colliding(grid_position * GRID_SIZE, ice.translation));
+        // Sliding and teleporting don't turn the die over, so it arrives with the same number face up
+        let face = die.orientation.face;
+        let is_blocked_sliding = |grid_position: Vec3| is_blocked(grid_position, face, None, &colliders_query, &filters_query, &other_dice);
+
+        if is_ice(die.destination_translation) {
+            // Keep going the way it was rolling until something is in the way or the ice runs out
+            let step = translation_from_direction(&die.animation_direction);
+            let mut slide_to = die.destination_translation;
+            while !is_blocked_sliding(slide_to + step) {
+                slide_to += step;
+                if !is_ice(slide_to) { break; }
+            }
+
+            if slide_to != die.destination_translation {
+                die.destination_translation = slide_to;
+                die.animation_state = DieAnimation::Sliding;
+                die.trigger_tile_on_landing = true;
+            }
+            continue;
+        }
+
+        let teleporter = teleporters_query
+            .iter()
+            .find(|(transform, _, _)| is_colliding(die.destination_translation * GRID_SIZE, transform.translation));
+        if let Some((transform, teleporter, colour)) = teleporter {
+            let exit = die_grid_translation(teleporter.partner);
+            if is_blocked_sliding(exit) { continue; }
+
+            // Arriving doesn't count as landing, or the die would bounce straight back
+            die.destination_translation = exit;
+            die_transform.translation = exit * GRID_SIZE;
+            for position in [transform.translation, exit * GRID_SIZE] {
+                spawn_teleport_flash(&mut commands, &spritesheet, position, colour.0);
+            }
+            continue;
+        }
+
+        let conveyor = conveyors_query
+            .iter()
+            .find(|(transform, _)| is_colliding(die.destination_translation * GRID_SIZE, transform.translation));
+        if let Some((_, conveyor)) = conveyor {
+            let face_after_roll = die.orientation.number_facing_after_roll(conveyor.0);
+            let next = die.destination_translation + translation_from_direction(&conveyor.0);
+            if is_blocked(next, face_after_roll, None, &colliders_query, &filters_query, &other_dice) { continue; }
+            // Level loading rules out belts and ice that carry the die round in circles, but never carry it forever regardless
+            if die.carried_rolls >= MAX_CARRIED_ROLLS { continue; }
+            start_roll(&mut die, conveyor.0);
+            die.carried_rolls += 1;
+            continue;
+        }
+
+        let rotator = rotators_query
+            .iter()
+            .find(|(transform, _)| is_colliding(die.destination_translation * GRID_SIZE, transform.translation));
+        if let Some((_, rotator)) = rotator {
+            die.orientation = die.orientation.spin(rotator.clockwise);
+            die.animation_state = DieAnimation::Spinning { clockwise: rotator.clockwise };
+            continue;
+        }
+
+        let switch = switches_query
+            .iter_mut()
+            .find(|(transform, _)| is_colliding(die.destination_translation * GRID_SIZE, transform.translation));
+        if let Some((_, mut switch)) = switch {
+            switch.on = !switch.on;
+            continue;
+        }
+
+        let paint = paints_query
+            .iter()
+            .find(|(transform, _)| is_colliding(die.destination_translation * GRID_SIZE, transform.translation));
+        if let Some((_, paint)) = paint {
+            die.orientation = die.orientation.paint(paint.0);
+        }
     }
 }
 
+// Shrinks and spins the die away into the pit, then fails the level
+fn tick_falling(
+    time: Res<Time>,
+    mut die_query: Query<(&Die, &mut Transform, &mut TextureAtlasSprite)>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    for (die, mut transform, mut sprite) in die_query.iter_mut() {
+        if die.animation_state != DieAnimation::Falling { continue; }
 
+        let shrink = PIXEL_SCALE * time.delta_seconds() / FALL_SECONDS;
+        transform.scale = (transform.scale - Vec3::splat(shrink)).max(Vec3::ZERO);
+        transform.rotate_z(SPIN_SPEED * time.delta_seconds());
+        sprite.color.set_a(transform.scale.x / PIXEL_SCALE);
+
+        if transform.scale.x <= 0.0 {
+            state.set(GameState::Failed);
+        }
+    }
+}
+
+#[derive(Component)]
+struct TeleportFlash(Timer);
+
+fn spawn_teleport_flash(commands: &mut Commands, spritesheet: &Spritesheet, position: Vec3, colour: Color) {
+    commands.spawn((
+        TeleportFlash(Timer::from_seconds(TELEPORT_FLASH_SECONDS, TimerMode::Once)),
+        SpriteSheetBundle {
+            texture_atlas: spritesheet.0.clone(),
+            transform: Transform {
+                translation: position.truncate().extend(DIE_HEIGHT + 0.5),
+                scale: Vec3::splat(PIXEL_SCALE),
+                ..default()
+            },
+            sprite: TextureAtlasSprite {
+                index: TELEPORTER_SPRITE_INDEX,
+                color: colour,
+                ..default()
+            },
+            ..default()
+        },
+    ));
+}
+
+// Each flash grows and fades away, then is removed
+fn tick_teleport_flashes(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut flashes_query: Query<(Entity, &mut TeleportFlash, &mut Transform, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut flash, mut transform, mut sprite) in flashes_query.iter_mut() {
+        flash.0.tick(time.delta());
+        if flash.0.finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let progress = flash.0.percent();
+        transform.scale = Vec3::splat(PIXEL_SCALE * (1.0 + progress));
+        sprite.color.set_a(1.0 - progress);
+    }
+}
+
+// Everything solid apart from the dice, which are checked by where they're heading instead
+type CollidersQuery<'w, 's> = Query<'w, 's, (Entity, &'static Transform), (With<Collider>, Without<Die>)>;
+
+// Whether the die can't end up at `grid_position` with `face` up, because a collider (other than `ignoring`, e.g. a
+// crate about to be pushed out of the way), face filter or one of the `other_dice` (in grid units) is in the way
+fn is_blocked(
+    grid_position: Vec3,
+    face: usize,
+    ignoring: Option<Entity>,
+    colliders_query: &CollidersQuery,
+    filters_query: &Query<(&Transform, &Filter), Without<Die>>,
+    other_dice: &[Vec3],
+) -> bool {
+    let position = grid_position * GRID_SIZE;
+    return colliders_query.iter().any(|(entity, collider)| Some(entity) != ignoring && is_colliding(position, collider.translation)) ||
+        filters_query.iter().any(|(transform, filter)| is_colliding(position, transform.translation) && !filter.0.allows(face)) ||
+        other_dice.iter().any(|other_die| is_colliding(position, *other_die * GRID_SIZE));
+}
+
+#[allow(clippy::needless_return)]
 fn is_colliding(object1_pos: Vec3, object2_pos: Vec3) -> bool {
     // Scrap the depth component. We don't need it. Also for some reason the die ends up at a different depth on replay.
     let object1_pos = object1_pos.truncate();
@@ -211,59 +831,158 @@ fn is_colliding(object1_pos: Vec3, object2_pos: Vec3) -> bool {
     return difference.length().abs() < GRID_SIZE / 2.0; // Just do sphere collision detection because everything is squares
 }
 
-fn rotate_die(die: &mut Die, rotation: &Direction) {
-    match rotation {
-        Direction::Up => {
-            let old_face_number = die.face_number;
-            die.face_number = die.bottom_number;
-            die.bottom_number = die.hidden_number;
-            die.hidden_number = die.top_number;
-            die.top_number = old_face_number;
-        },
-        Direction::Right => {
-            let old_face_number = die.face_number;
-            die.face_number = die.left_number;
-            die.left_number = die.hidden_number;
-            die.hidden_number = die.right_number;
-            die.right_number = old_face_number;
-        },
-        Direction::Down => {
-            let old_face_number = die.face_number;
-            die.face_number = die.top_number;
-            die.top_number = die.hidden_number;
-            die.hidden_number = die.bottom_number;
-            die.bottom_number = old_face_number;
-        },
-        Direction::Left => {
-            let old_face_number = die.face_number;
-            die.face_number = die.right_number;
-            die.right_number = die.hidden_number;
-            die.hidden_number = die.left_number;
-            die.left_number = old_face_number;
-        },
+#[allow(clippy::needless_return)]
+fn get_die_animation_frame_index(frame_num: usize, movement_direction: Direction) -> usize {
+    let frames: [usize; 3] = match movement_direction {
+        Direction::Up => [52,53,54],
+        Direction::Down => [54,53,52], 
+        Direction::Right => [49,50,51], 
+        Direction::Left => [51,50,49],   
     };
+
+    return frames[frame_num - 1];
 }
 
-fn get_die_animation_frame_index(frame_num: usize, movement_direction: Direction) -> usize {
-    let frames: [usize; 3];
-    match movement_direction {
-        Direction::Up => frames = [52,53,54],
-        Direction::Down => frames = [54,53,52], 
-        Direction::Right => frames = [49,50,51], 
-        Direction::Left => frames = [51,50,49],   
+// Column n of the die's rows shows n pips. Labels with more than that go on the blank face in column 0, with
+// `show_face_numbers` printing the number over it.
+#[allow(clippy::needless_return)]
+pub fn get_die_face_sprite_index(face_label: usize) -> usize {
+    if face_label > MAX_PIPS { return BLANK_FACE_SPRITE_INDICES[0]; }
+    return face_label;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_with_a_die() -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Input<KeyCode>>();
+        world.init_resource::<KeyBindings>();
+        world.init_resource::<MoveHistory>();
+        world.init_resource::<LevelStats>();
+        world.init_resource::<PlateOrder>();
+        let die = world.spawn(DieBundle::new(&Handle::default(), Vec2::ZERO, DieOrientation::default(), 0, None)).id();
+        (world, die)
     }
 
-    return frames[frame_num - 1];
-}
+    // Presses `key` for one run of the undo and redo systems, then lets the die finish its roll
+    fn press(world: &mut World, die: Entity, key: KeyCode) {
+        let mut schedule = Schedule::new();
+        schedule.add_systems((undo_move, redo_move).chain());
+        world.resource_mut::<Input<KeyCode>>().press(key);
+        schedule.run(world);
+        world.resource_mut::<Input<KeyCode>>().release(key);
+        world.get_mut::<Die>(die).unwrap().animation_state = DieAnimation::None;
+    }
+
+    #[test]
+    fn undo_takes_back_the_move_redo_counted() {
+        let (mut world, die) = world_with_a_die();
+        world.resource_mut::<MoveHistory>().redo.push(Move {
+            die,
+            direction: Direction::Right,
+            orientation_before: DieOrientation::default(),
+            destination_before: Vec3::ZERO,
+            destination_after: Vec3::X,
+            plates_before: Vec::new(),
+            switches_before: Vec::new(),
+            plate_order_before: 0,
+            crumbling_before: Vec::new(),
+            crates_before: Vec::new(),
+        });
+
+        press(&mut world, die, KeyCode::Y);
+        assert_eq!(world.resource::<LevelStats>().moves, 1);
+        press(&mut world, die, KeyCode::Z);
+        assert_eq!(world.resource::<LevelStats>().moves, 0);
+
+        // Nothing left to undo, and the count doesn't go below nothing
+        press(&mut world, die, KeyCode::Z);
+        assert_eq!(world.resource::<LevelStats>().moves, 0);
+    }
+
+    #[test]
+    fn undoing_a_push_takes_the_crate_straight_off_the_plate() {
+        let (mut world, die) = world_with_a_die();
+        let plate_before = PressurePlate::new(1, PlateMode::Latching, None);
+        let plate = world.spawn((
+            PressurePlate { activated: true, completed: true, ..plate_before },
+            Transform::from_translation(Vec3::new(2.0, 0.0, 0.0) * GRID_SIZE),
+        )).id();
+        let pushed = world.spawn((
+            Crate { destination: Vec3::new(2.0, 0.0, 0.0) },
+            Transform::from_translation(Vec3::new(2.0, 0.0, 0.0) * GRID_SIZE),
+        )).id();
+        world.resource_mut::<MoveHistory>().undo.push(Move {
+            die,
+            direction: Direction::Right,
+            orientation_before: DieOrientation::default(),
+            destination_before: Vec3::ZERO,
+            destination_after: Vec3::X,
+            plates_before: vec![(plate, plate_before)],
+            switches_before: Vec::new(),
+            plate_order_before: 0,
+            crumbling_before: Vec::new(),
+            crates_before: vec![(pushed, Vec3::X)],
+        });
+
+        press(&mut world, die, KeyCode::Z);
+        let mut schedule = Schedule::new();
+        schedule.add_systems(new_check_pressure_plates);
+        schedule.run(&mut world);
+
+        assert_eq!(world.get::<Transform>(pushed).unwrap().translation, Vec3::X * GRID_SIZE);
+        let plate = world.get::<PressurePlate>(plate).unwrap();
+        assert!(!plate.activated && !plate.completed);
+    }
+
+    #[test]
+    fn dice_landing_together_both_react_to_their_tiles() {
+        let (mut world, first) = world_with_a_die();
+        world.insert_resource(Spritesheet(Handle::default()));
+        let second = world.spawn(DieBundle::new(&Handle::default(), Vec2::X * 2.0, DieOrientation::default(), 1, None)).id();
+        for die in [first, second] {
+            let position = world.get::<Die>(die).unwrap().destination_translation;
+            world.spawn((Pit, Transform::from_translation(position * GRID_SIZE)));
+            world.get_mut::<Die>(die).unwrap().trigger_tile_on_landing = true;
+        }
+
+        let mut schedule = Schedule::new();
+        schedule.add_systems(react_to_tile);
+        schedule.run(&mut world);
+
+        for die in [first, second] {
+            assert!(world.get::<Die>(die).unwrap().animation_state == DieAnimation::Falling);
+        }
+    }
+
+    #[test]
+    fn labels_without_pips_are_printed_on_the_blank_face() {
+        for label in 0..=MAX_PIPS {
+            assert_eq!(get_die_face_sprite_index(label), label);
+        }
+
+        let (mut world, die) = world_with_a_die();
+        let number = world.spawn((FaceNumber, Text::from_section("", TextStyle::default()), Visibility::Hidden)).id();
+        world.entity_mut(die).add_child(number);
+        let mut schedule = Schedule::new();
+        schedule.add_systems((update_die_face_labels, show_face_numbers).chain());
+
+        world.get_mut::<Die>(die).unwrap().orientation.face = 12;
+        world.get_mut::<TextureAtlasSprite>(die).unwrap().index = get_die_face_sprite_index(12);
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Text>(number).unwrap().sections[0].value, "12");
+        assert_eq!(world.get::<Visibility>(number), Some(&Visibility::Inherited));
+
+        // Hidden while rolling, and for labels that have pips
+        world.get_mut::<TextureAtlasSprite>(die).unwrap().index = get_die_animation_frame_index(2, Direction::Up);
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Visibility>(number), Some(&Visibility::Hidden));
 
-pub fn get_die_face_sprite_index(face_num: usize) -> usize {
-    match face_num {
-        1 => return 1,
-        2 => return 2,
-        3 => return 3,
-        4 => return 4,
-        5 => return 5,
-        6 => return 6,
-        _ => return 0,
+        world.get_mut::<Die>(die).unwrap().orientation.face = 5;
+        world.get_mut::<TextureAtlasSprite>(die).unwrap().index = get_die_face_sprite_index(5);
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Visibility>(number), Some(&Visibility::Hidden));
     }
 }
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Direction {
     Up,
     Down,
@@ -8,6 +8,19 @@ pub enum Direction {
     Right
 }
 
+impl Direction {
+    #[allow(clippy::needless_return)]
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Up => return Direction::Down,
+            Direction::Down => return Direction::Up,
+            Direction::Left => return Direction::Right,
+            Direction::Right => return Direction::Left,
+        }
+    }
+}
+
+#[allow(clippy::needless_return)]
 pub fn translation_from_direction(direction: &Direction) -> Vec3 {
     match direction {
         Direction::Up => return Vec3::new(0.0, 1.0, 0.0),
@@ -17,10 +30,12 @@ pub fn translation_from_direction(direction: &Direction) -> Vec3 {
     }
 }
 
-pub fn keypress_to_direction(keyboard_input: Res<Input<KeyCode>>) -> Option<Direction> {
-    if keyboard_input.pressed(KeyCode::Left) { return Some(Direction::Left); }
-    if keyboard_input.pressed(KeyCode::Right) { return Some(Direction::Right); }
-    if keyboard_input.pressed(KeyCode::Up) { return Some(Direction::Up); }
-    if keyboard_input.pressed(KeyCode::Down) { return Some(Direction::Down); }
+// `keys` are for left, right, up and down, like `KeyBindings::player_two_keys`
+pub fn keys_to_direction(keyboard_input: &Input<KeyCode>, keys: [KeyCode; 4]) -> Option<Direction> {
+    let [left, right, up, down] = keys;
+    if keyboard_input.pressed(left) { return Some(Direction::Left); }
+    if keyboard_input.pressed(right) { return Some(Direction::Right); }
+    if keyboard_input.pressed(up) { return Some(Direction::Up); }
+    if keyboard_input.pressed(down) { return Some(Direction::Down); }
     None
 }
\ No newline at end of file
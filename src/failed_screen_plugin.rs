@@ -0,0 +1,117 @@
+use bevy::prelude::*;
+use crate::{GameState, key_bindings::{Action, KeyBindings}, world_plugin::RestartLevel};
+
+pub struct FailedScreenPlugin;
+
+impl Plugin for FailedScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Startup, setup)
+            .add_systems(
+                OnEnter(GameState::Failed),
+                (
+                    show,
+                    start_timer
+                )
+            )
+            .add_systems(
+                Update,
+                update.run_if(in_state(GameState::Failed))
+            )
+            .add_systems(
+                OnExit(GameState::Failed),
+                hide
+            );
+    }
+}
+
+#[derive(Component)]
+struct FailedUi;
+
+#[derive(Component)]
+struct RetryTimer(Timer);
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexEnd,
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(5.0),
+                right: Val::Px(15.0),
+                ..default()
+            },
+            text: Text::from_sections([
+                TextSection::new(
+                    "You fell in!\n",
+                    TextStyle {
+                        font: asset_server.load("fonts/FreeSans.ttf"),
+                        font_size: 100.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FreeSans.ttf"),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                ),
+            ]).with_alignment(TextAlignment::Center),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(FailedUi)
+        .insert(RetryTimer(Timer::from_seconds(3.0, TimerMode::Once)));
+}
+
+fn show(
+    key_bindings: Res<KeyBindings>,
+    mut query: Query<
+        (&mut Visibility, &mut Text),
+        With<FailedUi>>,
+) {
+    for (mut visibility, mut text) in query.iter_mut() {
+        *visibility = Visibility::Visible;
+        text.sections[1].value = format!("Press {:?} to try again", key_bindings.key(Action::Restart));
+    }
+}
+
+fn start_timer(
+    mut query: Query<&mut RetryTimer>,
+) {
+    let mut timer = query.single_mut();
+    timer.0.reset();
+}
+
+// Restarts the level once the player presses restart, or after a while if they don't
+fn update(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut query: Query<&mut RetryTimer>,
+    mut restart_events: EventWriter<RestartLevel>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    let mut timer = query.single_mut();
+    timer.0.tick(time.delta());
+
+    if timer.0.finished() || key_bindings.just_pressed(&keyboard_input, Action::Restart) {
+        restart_events.send(RestartLevel);
+        state.set(GameState::Playing);
+    }
+}
+
+fn hide(
+    mut query: Query<
+        &mut Visibility,
+        With<FailedUi>>,
+) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
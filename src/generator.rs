@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use crate::{
+    die_orientation::DieOrientation,
+    direction::Direction,
+    level::{Level, TileType, DEFAULT_HINT_LIMIT},
+    solver,
+};
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+pub struct GeneratorSettings {
+    pub width: usize, // Including the outer wall
+    pub height: usize,
+    pub plate_count: usize,
+    pub wall_chance: f32, // Chance of each inner tile being a wall
+    pub min_moves: usize, // Reject boards that can be solved in fewer rolls than this
+    pub attempts: usize,
+}
+
+impl Default for GeneratorSettings {
+    fn default() -> Self {
+        GeneratorSettings {
+            width: 9,
+            height: 8,
+            plate_count: 3,
+            wall_chance: 0.15,
+            min_moves: 8,
+            attempts: 50,
+        }
+    }
+}
+
+pub struct GeneratedLevel {
+    pub level: Level,
+    pub par: usize, // Fewest rolls needed to solve it
+}
+
+// Boards get bigger, busier and longer to solve the further into endless mode you get
+pub fn endless_settings(level_index: usize) -> GeneratorSettings {
+    GeneratorSettings {
+        width: (7 + level_index / 2).min(14),
+        height: (6 + level_index / 3).min(12),
+        plate_count: (2 + level_index / 2).min(6),
+        wall_chance: 0.1 + (level_index as f32 * 0.01).min(0.1),
+        min_moves: (4 + level_index * 2).min(24),
+        ..Default::default()
+    }
+}
+
+// The daily puzzle is a bit bigger and harder than the start of endless mode
+pub fn daily_settings() -> GeneratorSettings {
+    GeneratorSettings {
+        width: 11,
+        height: 9,
+        plate_count: 4,
+        wall_chance: 0.15,
+        min_moves: 16,
+        ..Default::default()
+    }
+}
+
+// How many seeds `generate_easing_off` tries before giving up on generating a board
+const MAX_SEEDS: u64 = 32;
+
+// A level known to be solvable, for when generating one fails
+const FALLBACK_LEVEL: &[u8] = include_bytes!("../assets/levels/level_01.level.ron");
+
+// Like `generate`, but if a seed can't make a hard enough board the next seed is tried with `min_moves` eased off.
+// There is always a level in the end, if need be the fallback one.
+#[allow(clippy::needless_return)]
+pub fn generate_easing_off(seed: u64, mut settings: GeneratorSettings) -> GeneratedLevel {
+    for attempt in 0..MAX_SEEDS {
+        if let Some(generated) = generate(seed.wrapping_add(attempt * 0x1000), &settings) { return generated; }
+        settings.min_moves = settings.min_moves.saturating_sub(1);
+    }
+
+    log::warn!("Couldn't generate a level from seed {}, using the fallback level instead", seed);
+    return fallback_level();
+}
+
+fn fallback_level() -> GeneratedLevel {
+    let level = Level::from_ron(FALLBACK_LEVEL).expect("fallback level can't be read");
+    let par = solver::solve(&level).expect("fallback level can't be solved").len();
+    GeneratedLevel { level, par }
+}
+
+// The same seed and settings always give the same board.
+// Returns None if no attempt produced a board that needs at least `min_moves` rolls.
+#[allow(clippy::needless_return)]
+pub fn generate(seed: u64, settings: &GeneratorSettings) -> Option<GeneratedLevel> {
+    let mut rng = Rng(seed);
+    for _ in 0..settings.attempts {
+        let Some(level) = try_generate(&mut rng, settings) else { continue; };
+        let Some(moves) = solver::solve(&level) else { continue; };
+        if moves.len() >= settings.min_moves {
+            return Some(GeneratedLevel { level, par: moves.len() });
+        }
+    }
+    return None;
+}
+
+// Plates are placed along a random walk of the die, showing the number the die had face up when it passed over them.
+// Replaying the walk activates every plate, so the board is always solvable.
+fn try_generate(rng: &mut Rng, settings: &GeneratorSettings) -> Option<Level> {
+    let (width, height) = (settings.width, settings.height);
+    let mut tiles = vec![vec![TileType::Floor; width]; height];
+    for (row, tile_row) in tiles.iter_mut().enumerate() {
+        for (column, tile) in tile_row.iter_mut().enumerate() {
+            let is_border = row == 0 || column == 0 || row == height - 1 || column == width - 1;
+            if is_border || rng.chance(settings.wall_chance) {
+                *tile = TileType::Wall;
+            }
+        }
+    }
+
+    let floor: Vec<(usize, usize)> = (0..height)
+        .flat_map(|row| (0..width).map(move |column| (column, row)))
+        .filter(|&(column, row)| tiles[row][column] == TileType::Floor)
+        .collect();
+    if floor.is_empty() { return None; }
+    let die_start = floor[rng.below(floor.len())];
+
+    let mut die_orientation = DieOrientation::default();
+    for _ in 0..rng.below(8) {
+        die_orientation = die_orientation.roll(DIRECTIONS[rng.below(4)]);
+    }
+
+    let mut walk = Vec::new();
+    let (mut position, mut orientation) = (die_start, die_orientation);
+    for _ in 0..width * height * 2 {
+        let open: Vec<Direction> = DIRECTIONS
+            .into_iter()
+            .filter(|direction| tiles_neighbour(&tiles, position, *direction).is_some())
+            .collect();
+        if open.is_empty() { return None; }
+
+        let direction = open[rng.below(open.len())];
+        position = tiles_neighbour(&tiles, position, direction)?;
+        orientation = orientation.roll(direction);
+        if position != die_start {
+            walk.push((position, orientation.face));
+        }
+    }
+
+    let mut plates_placed = 0;
+    while plates_placed < settings.plate_count && !walk.is_empty() {
+        let ((column, row), face) = walk.swap_remove(rng.below(walk.len()));
+        if tiles[row][column] != TileType::Floor { continue; }
+        let Some(plate) = TileType::pressure_plate(face) else { continue; };
+
+        tiles[row][column] = plate;
+        plates_placed += 1;
+    }
+    if plates_placed < settings.plate_count { return None; }
+
+    Some(Level {
+        tiles,
+        die_start,
+        die_orientation,
+        hint_limit: DEFAULT_HINT_LIMIT,
+        links: HashMap::new(),
+        plate_modes: HashMap::new(),
+        plate_order: Vec::new(),
+        face_filters: HashMap::new(),
+        paints: HashMap::new(),
+        crates: Vec::new(),
+        extra_dice: Vec::new(),
+    })
+}
+
+// The floor tile next to `position`, if there is one
+#[allow(clippy::needless_return)]
+fn tiles_neighbour(tiles: &[Vec<TileType>], position: (usize, usize), direction: Direction) -> Option<(usize, usize)> {
+    let (column, row) = position;
+    let (column, row) = match direction {
+        Direction::Up => (column, row.checked_sub(1)?),
+        Direction::Down => (column, row + 1),
+        Direction::Left => (column.checked_sub(1)?, row),
+        Direction::Right => (column + 1, row),
+    };
+
+    if *tiles.get(row)?.get(column)? == TileType::Wall { return None; }
+    return Some((column, row));
+}
+
+// SplitMix64. Hand rolled so a seed makes the same board on every platform and version.
+struct Rng(u64);
+
+impl Rng {
+    #[allow(clippy::needless_return)]
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        return z ^ (z >> 31);
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        ((self.next_u64() >> 40) as f32 / (1u64 << 24) as f32) < probability
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_levels_meet_the_settings() {
+        let settings = GeneratorSettings::default();
+        for seed in 0..10 {
+            let generated = generate(seed, &settings).expect("no level generated");
+            let level = &generated.level;
+            assert_eq!((level.width(), level.height()), (settings.width, settings.height));
+
+            let plates = level.tiles.iter().flatten().filter(|tile| tile.pressure_plate_number().is_some()).count();
+            assert_eq!(plates, settings.plate_count);
+
+            let moves = solver::solve(level).expect("generated level can't be solved");
+            assert_eq!(moves.len(), generated.par);
+            assert!(generated.par >= settings.min_moves);
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_level() {
+        let settings = GeneratorSettings::default();
+        let first = generate(1234, &settings).unwrap().level;
+        let second = generate(1234, &settings).unwrap().level;
+        assert_eq!(first.tiles, second.tiles);
+        assert_eq!(first.die_start, second.die_start);
+        assert_eq!(first.die_orientation, second.die_orientation);
+    }
+
+    #[test]
+    fn impossible_difficulty_gives_up() {
+        let settings = GeneratorSettings { width: 4, height: 4, min_moves: 100, attempts: 5, ..Default::default() };
+        assert!(generate(0, &settings).is_none());
+    }
+
+    #[test]
+    fn boards_that_cant_be_generated_fall_back_to_a_known_level() {
+        // Nothing but walls, so no seed can ever place the die
+        let settings = GeneratorSettings { width: 2, height: 2, attempts: 1, ..Default::default() };
+        let generated = generate_easing_off(0, settings);
+        assert_eq!(generated.level.tiles, Level::from_ron(FALLBACK_LEVEL).unwrap().tiles);
+        assert_eq!(Some(generated.par), solver::solve(&generated.level).map(|moves| moves.len()));
+    }
+
+    #[test]
+    fn daily_puzzle_can_be_generated() {
+        assert!(generate(20261018, &daily_settings()).is_some());
+    }
+
+    #[test]
+    fn early_endless_levels_can_be_generated() {
+        for level_index in 0..6 {
+            assert!(generate(level_index as u64, &endless_settings(level_index)).is_some(), "level {}", level_index);
+        }
+    }
+}
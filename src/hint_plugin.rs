@@ -0,0 +1,223 @@
+use bevy::prelude::*;
+
+use crate::{
+    GameState, GRID_SIZE, PressurePlate,
+    controls_plugin::ControlsDisplay,
+    die_plugin::{Die, all_dice_at_rest},
+    direction::{Direction, translation_from_direction},
+    key_bindings::{Action, KeyBindings},
+    level::Level,
+    solver::{Puzzle, PuzzleState, Search, SearchProgress, sorted_crates, sorted_dice},
+    world_plugin::{Campaign, CampaignMode, Crate, Crumbling, CurrentLevel, LevelSolution, LevelSpawned, PlateOrder, Switch, Tile, TileColour},
+};
+
+pub struct HintPlugin;
+
+impl Plugin for HintPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<Hints>()
+            .add_systems(Update, reset_hints)
+            .add_systems(
+                Update,
+                (
+                    request_hint,
+                    clear_hint_after_move,
+                    show_hint,
+                ).chain().run_if(in_state(GameState::Playing))
+            );
+    }
+}
+
+const HINT_COLOUR: Color = Color::YELLOW;
+// Solving again from where the player has got to is given up on after this many states, so a hint never holds the game
+// up for long. That's a fraction of a second in a release build.
+const HINT_SEARCH_STATES: usize = 50_000;
+
+#[derive(Resource, Default)]
+struct Hints {
+    remaining: usize,
+    shown: Option<Hint>,
+}
+
+struct Hint {
+    direction: Direction,
+    target_tile: Vec2, // In grid units, like `Die::destination_translation`
+    given_at: Vec<Vec3>, // Where each die was heading when the hint was given
+}
+
+fn reset_hints(
+    mut level_spawned_events: EventReader<LevelSpawned>,
+    campaign: Res<Campaign>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut hints: ResMut<Hints>,
+) {
+    let Some(level_spawned) = level_spawned_events.iter().last() else { return; };
+    let Some(level) = campaign.level(&current_level, &levels) else { return; };
+
+    // Restarting doesn't give the hints back
+    if !level_spawned.restarted {
+        hints.remaining = level.hint_limit;
+    }
+    hints.shown = None;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn request_hint(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    campaign: Res<Campaign>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
+    mut hints: ResMut<Hints>,
+    mut level_solution: ResMut<LevelSolution>,
+    die_query: Query<&Die>,
+    pressure_plates_query: Query<(&PressurePlate, &Transform)>,
+    switches_query: Query<(&Switch, &Transform)>,
+    plate_order: Res<PlateOrder>,
+    crumbling_query: Query<(&Crumbling, &Transform)>,
+    crates_query: Query<&Crate>,
+) {
+    if !key_bindings.just_pressed(&keyboard_input, Action::Hint) || hints.shown.is_some() { return; }
+    // Part way through a roll or a fall there's no telling where the die ends up yet
+    if !all_dice_at_rest(die_query.iter()) { return; }
+    if campaign.mode == CampaignMode::Versus {
+        log::info!("No hints in two-player mode");
+        return;
+    }
+    if hints.remaining == 0 {
+        log::info!("No hints left for this level");
+        return;
+    }
+    let Some(level) = campaign.level(&current_level, &levels) else { return; };
+
+    let puzzle = Puzzle::new(level);
+    let Some(state) = current_puzzle_state(level, &puzzle, &die_query, &pressure_plates_query, &switches_query, &plate_order, &crumbling_query, &crates_query) else { return; };
+    // Carry on along the solution already worked out, unless the player has gone their own way since
+    let on_solution = level_solution.states.iter().position(|solution_state| *solution_state == Puzzle::canonical(&state));
+    let roll = match (on_solution, &level_solution.rolls) {
+        (Some(index), Some(rolls)) => rolls[index],
+        _ => {
+            let moves = match Search::new(state).advance(&puzzle, HINT_SEARCH_STATES) {
+                SearchProgress::Solved(moves) => moves,
+                SearchProgress::Unsolvable => {
+                    log::info!("The level can't be solved from here");
+                    return;
+                },
+                SearchProgress::Searching => {
+                    log::info!("No hint, solving the level from here would take too long. Try undoing back towards where you were");
+                    return;
+                },
+            };
+            let Some(&roll) = moves.first() else { return; };
+            *level_solution = LevelSolution::new(&puzzle, state, Some(moves));
+            roll
+        },
+    };
+    let direction = roll.direction;
+    let (column, row) = roll.die;
+
+    // The hint may be for one of the other dice, in which case the highlighted tile shows the player which to switch to
+    hints.remaining -= 1;
+    hints.shown = Some(Hint {
+        direction,
+        target_tile: level.grid_position(column, row) + translation_from_direction(&direction).truncate(),
+        given_at: dice_positions(&die_query),
+    });
+    log::info!("Hint: roll {:?}. {} hints left", direction, hints.remaining);
+}
+
+fn clear_hint_after_move(
+    mut hints: ResMut<Hints>,
+    die_query: Query<&Die>,
+) {
+    let Some(hint) = &hints.shown else { return; };
+    if dice_positions(&die_query) != hint.given_at {
+        hints.shown = None;
+    }
+}
+
+fn dice_positions(die_query: &Query<&Die>) -> Vec<Vec3> {
+    let mut dice: Vec<&Die> = die_query.iter().collect();
+    dice.sort_by_key(|die| die.index);
+    return dice.iter().map(|die| die.destination_translation).collect();
+}
+
+fn show_hint(
+    hints: Res<Hints>,
+    mut controls_display_query: Query<(&ControlsDisplay, &mut TextureAtlasSprite), Without<Tile>>,
+    mut tiles_query: Query<(&Transform, &mut TextureAtlasSprite, Option<&TileColour>), With<Tile>>,
+) {
+    if !hints.is_changed() { return; }
+
+    for (control_display, mut sprite) in controls_display_query.iter_mut() {
+        let hinted = hints.shown.as_ref().is_some_and(|hint| hint.direction == control_display.0);
+        sprite.color = if hinted { HINT_COLOUR } else { Color::WHITE };
+    }
+
+    for (transform, mut sprite, tile_colour) in tiles_query.iter_mut() {
+        let hinted = hints.shown.as_ref().is_some_and(|hint|
+            (transform.translation.truncate() / GRID_SIZE).distance(hint.target_tile) < 0.5);
+        let colour = tile_colour.map_or(Color::WHITE, |tile_colour| tile_colour.0);
+        sprite.color = if hinted { HINT_COLOUR } else { colour };
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn current_puzzle_state(
+    level: &Level,
+    puzzle: &Puzzle,
+    die_query: &Query<&Die>,
+    pressure_plates_query: &Query<(&PressurePlate, &Transform)>,
+    switches_query: &Query<(&Switch, &Transform)>,
+    plate_order: &PlateOrder,
+    crumbling_query: &Query<(&Crumbling, &Transform)>,
+    crates_query: &Query<&Crate>,
+) -> Option<PuzzleState> {
+    let mut activated_plates = 0;
+    let mut completed_plates = 0;
+    for (pressure_plate, transform) in pressure_plates_query.iter() {
+        let position = level.grid_index(transform.translation.truncate() / GRID_SIZE)?;
+        let plate_bit = 1 << puzzle.plate_index(position)?;
+        if pressure_plate.activated { activated_plates |= plate_bit; }
+        if pressure_plate.completed { completed_plates |= plate_bit; }
+    }
+
+    let mut switches_on = 0;
+    for (switch, transform) in switches_query.iter() {
+        if !switch.on { continue; }
+        let position = level.grid_index(transform.translation.truncate() / GRID_SIZE)?;
+        switches_on |= 1 << puzzle.switch_index(position)?;
+    }
+
+    let mut crumbled = 0;
+    for (crumbling, transform) in crumbling_query.iter() {
+        if !crumbling.crumbled { continue; }
+        let position = level.grid_index(transform.translation.truncate() / GRID_SIZE)?;
+        crumbled |= 1 << puzzle.crumbling_index(position)?;
+    }
+
+    let mut crates = Vec::new();
+    for Crate { destination } in crates_query.iter() {
+        crates.push(level.grid_index(destination.truncate())?);
+    }
+
+    let die = die_query.iter().find(|die| die.active)?;
+    let mut other_dice = Vec::new();
+    for other_die in die_query.iter().filter(|other_die| !other_die.active) {
+        other_dice.push((level.grid_index(other_die.destination_translation.truncate())?, other_die.orientation));
+    }
+
+    Some(PuzzleState {
+        position: level.grid_index(die.destination_translation.truncate())?,
+        orientation: die.orientation,
+        activated_plates,
+        completed_plates,
+        plate_order_next: plate_order.next,
+        switches_on,
+        crumbled,
+        crates: sorted_crates(&crates),
+        other_dice: sorted_dice(&other_dice),
+    })
+}
@@ -0,0 +1,100 @@
+use bevy::prelude::*;
+use crate::{GameState, PressurePlate, world_plugin::{Campaign, CampaignMode, LevelSearch, LevelStats, PlateOrder}};
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .add_systems(Startup, setup)
+            // Not on leaving the title screen, in case that was to go to the settings
+            .add_systems(
+                OnEnter(GameState::Playing),
+                show
+            )
+            .add_systems(
+                Update,
+                update.run_if(not(in_state(GameState::MainMenu)))
+            )
+            .add_systems(
+                OnEnter(GameState::MainMenu),
+                hide
+            );
+    }
+}
+#[derive(Component)]
+struct HudUi;
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                left: Val::Px(15.0),
+                ..default()
+            },
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FreeSans.ttf"),
+                    font_size: 40.0,
+                    color: Color::WHITE,
+                },
+            ),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(HudUi);
+}
+
+fn show(
+    mut query: Query<
+        &mut Visibility,
+        With<HudUi>>,
+) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Visible;
+    }
+}
+
+fn update(
+    campaign: Res<Campaign>,
+    level_stats: Res<LevelStats>,
+    level_search: Res<LevelSearch>,
+    plate_order: Res<PlateOrder>,
+    pressure_plates_query: Query<&PressurePlate>,
+    mut query: Query<
+        &mut Text,
+        With<HudUi>>,
+) {
+    for mut text in query.iter_mut() {
+        // Races aren't about moves, just who gets to the plates first
+        text.sections[0].value = if campaign.mode == CampaignMode::Versus {
+            let plates_won = PressurePlate::plates_won(pressure_plates_query.iter());
+            format!("Plates: {} to {}", plates_won[0], plates_won[1])
+        } else {
+            match level_stats.par {
+                Some(par) => format!("Moves: {}    Par: {}", level_stats.moves, par),
+                None if level_search.is_solving() => format!("Moves: {}    Par: …", level_stats.moves),
+                None => format!("Moves: {}", level_stats.moves),
+            }
+        };
+        if let Some(number) = plate_order.next_number() {
+            text.sections[0].value += &format!("    Next plate: {}", number);
+        }
+    }
+}
+
+fn hide(
+    mut query: Query<
+        &mut Visibility,
+        With<HudUi>>,
+) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
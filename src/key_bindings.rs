@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::direction::{Direction, keys_to_direction};
+
+// Everything the player can do with a key
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Undo,
+    Redo,
+    Restart,
+    Hint,
+    SwitchDie,
+    Pause,
+    Endless, // The title screen's shortcuts for the other modes
+    Daily,
+    TwoPlayer,
+}
+
+// In the order the settings menu lists them
+pub const ACTIONS: [Action; 13] = [
+    Action::MoveUp,
+    Action::MoveDown,
+    Action::MoveLeft,
+    Action::MoveRight,
+    Action::Undo,
+    Action::Redo,
+    Action::Restart,
+    Action::Hint,
+    Action::SwitchDie,
+    Action::Pause,
+    Action::Endless,
+    Action::Daily,
+    Action::TwoPlayer,
+];
+
+// Only used on the title screen, so their keys are free for player two once a race has started
+const TITLE_SCREEN_ACTIONS: [Action; 3] = [Action::Endless, Action::Daily, Action::TwoPlayer];
+
+impl Action {
+    #[allow(clippy::needless_return)]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveUp => return "Move up",
+            Action::MoveDown => return "Move down",
+            Action::MoveLeft => return "Move left",
+            Action::MoveRight => return "Move right",
+            Action::Undo => return "Undo",
+            Action::Redo => return "Redo",
+            Action::Restart => return "Restart",
+            Action::Hint => return "Hint",
+            Action::SwitchDie => return "Switch die",
+            Action::Pause => return "Pause",
+            Action::Endless => return "Endless mode",
+            Action::Daily => return "Daily puzzle",
+            Action::TwoPlayer => return "Two players",
+        }
+    }
+}
+
+// Starting points for the bindings, which only differ in the keys used to move
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Preset {
+    Arrows,
+    Wasd,
+    Hjkl,
+}
+
+pub const PRESETS: [Preset; 3] = [Preset::Arrows, Preset::Wasd, Preset::Hjkl];
+
+impl Preset {
+    #[allow(clippy::needless_return)]
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Arrows => return "Arrow keys",
+            Preset::Wasd => return "WASD",
+            Preset::Hjkl => return "HJKL",
+        }
+    }
+}
+
+const ARROW_KEYS: [KeyCode; 4] = [KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down];
+const WASD_KEYS: [KeyCode; 4] = [KeyCode::A, KeyCode::D, KeyCode::W, KeyCode::S];
+
+// Where the bindings are kept between sessions: local storage on the web, a file next to the game otherwise
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY: &str = "key_bindings";
+#[cfg(not(target_arch = "wasm32"))]
+const BINDINGS_FILE: &str = "key_bindings.ron";
+
+// The key for each action. Every action has exactly one key and no two actions share one.
+#[derive(Resource, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct KeyBindings {
+    keys: HashMap<Action, KeyCode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings::preset(Preset::Arrows)
+    }
+}
+
+impl KeyBindings {
+    pub fn preset(preset: Preset) -> KeyBindings {
+        let [left, right, up, down] = match preset {
+            Preset::Arrows => ARROW_KEYS,
+            Preset::Wasd => WASD_KEYS,
+            Preset::Hjkl => [KeyCode::H, KeyCode::L, KeyCode::K, KeyCode::J],
+        };
+        // H is taken for moving left in HJKL, so hints go on the question mark key instead
+        let hint = if preset == Preset::Hjkl { KeyCode::Slash } else { KeyCode::H };
+        // Likewise D for moving right in WASD, so the daily puzzle goes on P
+        let daily = if preset == Preset::Wasd { KeyCode::P } else { KeyCode::D };
+
+        KeyBindings {
+            keys: HashMap::from([
+                (Action::MoveUp, up),
+                (Action::MoveDown, down),
+                (Action::MoveLeft, left),
+                (Action::MoveRight, right),
+                (Action::Undo, KeyCode::Z),
+                (Action::Redo, KeyCode::Y),
+                (Action::Restart, KeyCode::R),
+                (Action::Hint, hint),
+                (Action::SwitchDie, KeyCode::Tab),
+                (Action::Pause, KeyCode::Escape),
+                (Action::Endless, KeyCode::E),
+                (Action::Daily, daily),
+                (Action::TwoPlayer, KeyCode::Key2),
+            ]),
+        }
+    }
+
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.keys[&action]
+    }
+
+    pub fn pressed(&self, keyboard_input: &Input<KeyCode>, action: Action) -> bool {
+        keyboard_input.pressed(self.key(action))
+    }
+
+    pub fn just_pressed(&self, keyboard_input: &Input<KeyCode>, action: Action) -> bool {
+        keyboard_input.just_pressed(self.key(action))
+    }
+
+    // Which way the move keys held down say to roll
+    pub fn direction(&self, keyboard_input: &Input<KeyCode>) -> Option<Direction> {
+        keys_to_direction(keyboard_input, self.move_keys())
+    }
+
+    // Listed left, right, up, down like `keys_to_direction` wants them
+    fn move_keys(&self) -> [KeyCode; 4] {
+        [Action::MoveLeft, Action::MoveRight, Action::MoveUp, Action::MoveDown].map(|action| self.key(action))
+    }
+
+    // What to call the move keys on screen, e.g. "WASD", or each key if they aren't one of the presets
+    #[allow(clippy::needless_return)]
+    pub fn move_keys_name(&self) -> String {
+        let move_keys = self.move_keys();
+        match PRESETS.iter().find(|preset| KeyBindings::preset(**preset).move_keys() == move_keys) {
+            Some(preset) => return preset.name().to_string(),
+            None => return move_keys.map(|key| format!("{:?}", key)).join("/"),
+        }
+    }
+
+    // Player two moves with WASD, or the arrow keys if any of WASD are bound to something else. None if both are,
+    // as then player two's keys would also do something for player one. Listed left, right, up, down.
+    pub fn player_two_keys(&self) -> Option<[KeyCode; 4]> {
+        let in_game_keys: Vec<KeyCode> = self.keys
+            .iter()
+            .filter(|(action, _)| !TITLE_SCREEN_ACTIONS.contains(action))
+            .map(|(_, key)| *key)
+            .collect();
+        [WASD_KEYS, ARROW_KEYS]
+            .into_iter()
+            .find(|keys| !in_game_keys.iter().any(|bound| keys.contains(bound)))
+    }
+
+    // Binds `key` to `action`. Whatever had `key` before gets the action's old key, so nothing is left without one.
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        let old_key = self.key(action);
+        for bound in self.keys.values_mut() {
+            if *bound == key {
+                *bound = old_key;
+            }
+        }
+        self.keys.insert(action, key);
+    }
+
+    // The saved bindings, or the defaults if there aren't any or they can't be read
+    #[allow(clippy::needless_return)]
+    pub fn load() -> KeyBindings {
+        let Some(saved) = read_saved() else { return KeyBindings::default(); };
+        match KeyBindings::from_ron(&saved) {
+            Some(bindings) => return bindings,
+            None => {
+                log::warn!("Ignoring saved key bindings that couldn't be read: {}", saved);
+                return KeyBindings::default();
+            },
+        }
+    }
+
+    pub fn save(&self) {
+        let Ok(ron) = ron::to_string(self) else { return; };
+        write_saved(&ron);
+    }
+
+    // Rejects bindings missing an action or sharing a key, e.g. saved by an older version
+    #[allow(clippy::needless_return)]
+    fn from_ron(ron: &str) -> Option<KeyBindings> {
+        let bindings: KeyBindings = ron::from_str(ron).ok()?;
+        let mut keys: Vec<KeyCode> = ACTIONS.iter().map(|action| bindings.keys.get(action).copied()).collect::<Option<_>>()?;
+        keys.sort();
+        keys.dedup();
+        if keys.len() != ACTIONS.len() { return None; }
+        return Some(bindings);
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_saved() -> Option<String> {
+    let storage = web_sys::window()?.local_storage().ok()??;
+    storage.get_item(STORAGE_KEY).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_saved(ron: &str) {
+    let Some(Ok(Some(storage))) = web_sys::window().map(|window| window.local_storage()) else { return; };
+    if storage.set_item(STORAGE_KEY, ron).is_err() {
+        log::warn!("Couldn't save the key bindings");
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_saved() -> Option<String> {
+    std::fs::read_to_string(BINDINGS_FILE).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_saved(ron: &str) {
+    if let Err(error) = std::fs::write(BINDINGS_FILE, ron) {
+        log::warn!("Couldn't save the key bindings to {}: {}", BINDINGS_FILE, error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_give_every_action_its_own_key() {
+        for preset in PRESETS {
+            let bindings = KeyBindings::preset(preset);
+            let ron = ron::to_string(&bindings).unwrap();
+            assert_eq!(KeyBindings::from_ron(&ron), Some(bindings), "{:?}", preset);
+        }
+    }
+
+    #[test]
+    fn binding_a_key_in_use_swaps_it() {
+        let mut bindings = KeyBindings::default();
+        bindings.bind(Action::Undo, KeyCode::R);
+        assert_eq!(bindings.key(Action::Undo), KeyCode::R);
+        assert_eq!(bindings.key(Action::Restart), KeyCode::Z);
+
+        bindings.bind(Action::Undo, KeyCode::U);
+        assert_eq!(bindings.key(Action::Undo), KeyCode::U);
+        assert_eq!(bindings.key(Action::Restart), KeyCode::Z);
+    }
+
+    #[test]
+    fn saved_bindings_have_to_be_complete() {
+        assert_eq!(KeyBindings::from_ron("(keys: {MoveUp: Up})"), None);
+        assert_eq!(KeyBindings::from_ron("not bindings"), None);
+    }
+
+    #[test]
+    fn player_two_keeps_clear_of_player_one() {
+        // The daily puzzle's D is only used on the title screen, so doesn't get in player two's way
+        assert_eq!(KeyBindings::preset(Preset::Arrows).player_two_keys(), Some(WASD_KEYS));
+        assert_eq!(KeyBindings::preset(Preset::Wasd).player_two_keys(), Some(ARROW_KEYS));
+        assert_eq!(KeyBindings::preset(Preset::Hjkl).player_two_keys(), Some(WASD_KEYS));
+
+        // Undo on W rules out WASD, and the arrow keys are player one's
+        let mut bindings = KeyBindings::preset(Preset::Arrows);
+        bindings.bind(Action::Undo, KeyCode::W);
+        assert_eq!(bindings.player_two_keys(), None);
+
+        // Moving with something else frees the arrow keys up
+        for (action, key) in [(Action::MoveLeft, KeyCode::J), (Action::MoveRight, KeyCode::L), (Action::MoveUp, KeyCode::I), (Action::MoveDown, KeyCode::K)] {
+            bindings.bind(action, key);
+        }
+        assert_eq!(bindings.player_two_keys(), Some(ARROW_KEYS));
+    }
+}
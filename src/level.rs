@@ -0,0 +1,736 @@
+use std::{collections::{HashMap, HashSet}, fmt};
+
+use bevy::{
+    prelude::*,
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::{TypePath, TypeUuid},
+    utils::BoxedFuture,
+};
+use serde::Deserialize;
+
+use crate::{die_orientation::{DieOrientation, MAX_FACE_LABEL}, direction::Direction};
+
+// Levels are RON files with the tile grid written as one string per row:
+//   '#' wall, '.' floor, '1'-'6' pressure plate needing that number face up, '~' ice,
+//   'a'-'z' teleporter, linked to the one other teleporter with the same letter,
+//   '^' 'V' '<' '>' conveyor belt pushing the die that way,
+//   ')' '(' rotator spinning the die a quarter turn clockwise or counter-clockwise,
+//   '|' closed door, '/' open door, '*' switch, 'O' pit, '%' crumbling floor that becomes a pit once the die leaves it,
+//   '?' face filter that the die can only roll onto with the number it lets through face up,
+//   '@' paint that relabels the side of the die landing on it.
+// The die start is a (column, row) index into that grid.
+// `extra_dice` lists any more dice, each with its own start position and orientation. The player switches between them.
+// `links` maps the (column, row) of doors, switches and plates to a link ID. Every time a plate is activated or a
+// switch is pressed, the doors with the same link ID swap between open and closed.
+// `plate_modes` maps the (column, row) of plates to a `PlateMode`, for any that aren't latching.
+// `plate_order` lists the (column, row) of plates that have to be activated in that order. Activating one of them out
+// of turn starts the sequence again.
+// `face_filters` maps the (column, row) of each face filter to the `FaceFilter` it lets through.
+// `paints` maps the (column, row) of each paint tile to the label it paints, 0 (blank) to 6.
+// `crates` lists the (column, row) of each crate. The die pushes crates along when it rolls into them, and a crate
+// holds down any plate it sits on.
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TileType {
+    Wall,
+    Floor,
+    PressurePlate1,
+    PressurePlate2,
+    PressurePlate3,
+    PressurePlate4,
+    PressurePlate5,
+    PressurePlate6,
+    Ice,
+    Teleporter(char),
+    Conveyor(Direction),
+    Rotator { clockwise: bool },
+    Door { open: bool }, // Whether it starts open
+    Switch,
+    Pit,
+    Crumbling,
+    FaceFilter,
+    Paint,
+}
+
+impl TileType {
+    #[allow(clippy::needless_return)]
+    fn from_char(c: char) -> Option<TileType> {
+        match c {
+            '#' => return Some(TileType::Wall),
+            '.' => return Some(TileType::Floor),
+            '1' => return Some(TileType::PressurePlate1),
+            '2' => return Some(TileType::PressurePlate2),
+            '3' => return Some(TileType::PressurePlate3),
+            '4' => return Some(TileType::PressurePlate4),
+            '5' => return Some(TileType::PressurePlate5),
+            '6' => return Some(TileType::PressurePlate6),
+            '~' => return Some(TileType::Ice),
+            'a'..='z' => return Some(TileType::Teleporter(c)),
+            '^' => return Some(TileType::Conveyor(Direction::Up)),
+            'V' => return Some(TileType::Conveyor(Direction::Down)),
+            '<' => return Some(TileType::Conveyor(Direction::Left)),
+            '>' => return Some(TileType::Conveyor(Direction::Right)),
+            ')' => return Some(TileType::Rotator { clockwise: true }),
+            '(' => return Some(TileType::Rotator { clockwise: false }),
+            '|' => return Some(TileType::Door { open: false }),
+            '/' => return Some(TileType::Door { open: true }),
+            '*' => return Some(TileType::Switch),
+            'O' => return Some(TileType::Pit),
+            '%' => return Some(TileType::Crumbling),
+            '?' => return Some(TileType::FaceFilter),
+            '@' => return Some(TileType::Paint),
+            _ => return None,
+        }
+    }
+
+    // The plate needing `number` face up, or None if there's no plate for it
+    #[allow(clippy::needless_return)]
+    pub fn pressure_plate(number: usize) -> Option<TileType> {
+        match number {
+            1 => return Some(TileType::PressurePlate1),
+            2 => return Some(TileType::PressurePlate2),
+            3 => return Some(TileType::PressurePlate3),
+            4 => return Some(TileType::PressurePlate4),
+            5 => return Some(TileType::PressurePlate5),
+            6 => return Some(TileType::PressurePlate6),
+            _ => return None,
+        }
+    }
+
+    #[allow(clippy::needless_return)]
+    pub fn pressure_plate_number(&self) -> Option<usize> {
+        match self {
+            TileType::PressurePlate1 => return Some(1),
+            TileType::PressurePlate2 => return Some(2),
+            TileType::PressurePlate3 => return Some(3),
+            TileType::PressurePlate4 => return Some(4),
+            TileType::PressurePlate5 => return Some(5),
+            TileType::PressurePlate6 => return Some(6),
+            TileType::Wall |
+            TileType::Floor |
+            TileType::Ice |
+            TileType::Teleporter(_) |
+            TileType::Conveyor(_) |
+            TileType::Rotator { .. } |
+            TileType::Door { .. } |
+            TileType::Switch |
+            TileType::Pit |
+            TileType::Crumbling |
+            TileType::FaceFilter |
+            TileType::Paint => return None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Deserialize)]
+pub enum PlateMode {
+    #[default]
+    Latching, // Stays activated once the right number lands on it
+    Momentary, // Only activated while the die sits on it with the right number up, but counts as done once it has been
+    Held, // Like momentary, but has to be activated at the moment the level is won, along with every other held plate
+}
+
+// Which numbers a face filter lets the die roll onto it with
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+pub enum FaceFilter {
+    Number(usize),
+    Odd,
+    Even,
+}
+
+impl FaceFilter {
+    #[allow(clippy::needless_return)]
+    pub fn allows(&self, face: usize) -> bool {
+        match self {
+            FaceFilter::Number(number) => return face == *number,
+            FaceFilter::Odd => return face % 2 == 1,
+            FaceFilter::Even => return face % 2 == 0,
+        }
+    }
+}
+
+// The solver tracks activated plates, pressed switches and crumbled floors as bits in a u64
+pub const MAX_PRESSURE_PLATES: usize = 64;
+pub const MAX_SWITCHES: usize = 64;
+pub const MAX_CRUMBLING_FLOORS: usize = 64;
+// The solver packs positions into a u8 each
+pub const MAX_LEVEL_SIZE: usize = u8::MAX as usize;
+// The solver keeps crate and dice positions in fixed size arrays, so there can't be too many of them
+pub const MAX_CRATES: usize = 8;
+pub const MAX_DICE: usize = 4;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Deserialize)]
+pub struct DieStart {
+    pub position: (usize, usize),
+    pub orientation: DieOrientation,
+}
+
+#[derive(Debug, TypeUuid, TypePath)]
+#[uuid = "00a5dbce-7814-40b9-be04-0c25aff9ae9c"]
+pub struct Level {
+    pub tiles: Vec<Vec<TileType>>, // Indexed [row][column]
+    pub die_start: (usize, usize),
+    pub die_orientation: DieOrientation,
+    pub hint_limit: usize,
+    pub links: HashMap<(usize, usize), usize>, // Link ID of each linked (column, row)
+    pub plate_modes: HashMap<(usize, usize), PlateMode>, // Plates not listed are latching
+    pub plate_order: Vec<(usize, usize)>,
+    pub face_filters: HashMap<(usize, usize), FaceFilter>,
+    pub paints: HashMap<(usize, usize), usize>, // The label each paint tile gives the die
+    pub crates: Vec<(usize, usize)>,
+    pub extra_dice: Vec<DieStart>,
+}
+
+#[derive(Deserialize)]
+struct LevelFile {
+    tiles: Vec<String>,
+    die_start: (usize, usize),
+    die_orientation: DieOrientation,
+    #[serde(default = "default_hint_limit")]
+    hint_limit: usize,
+    #[serde(default)]
+    links: HashMap<(usize, usize), usize>,
+    #[serde(default)]
+    plate_modes: HashMap<(usize, usize), PlateMode>,
+    #[serde(default)]
+    plate_order: Vec<(usize, usize)>,
+    #[serde(default)]
+    face_filters: HashMap<(usize, usize), FaceFilter>,
+    #[serde(default)]
+    paints: HashMap<(usize, usize), usize>,
+    #[serde(default)]
+    crates: Vec<(usize, usize)>,
+    #[serde(default)]
+    extra_dice: Vec<DieStart>,
+}
+
+pub const DEFAULT_HINT_LIMIT: usize = 3;
+
+fn default_hint_limit() -> usize {
+    DEFAULT_HINT_LIMIT
+}
+
+impl Level {
+    pub fn from_ron(bytes: &[u8]) -> Result<Level, LevelError> {
+        let file: LevelFile = ron::de::from_bytes(bytes)
+            .map_err(|err| LevelError::Syntax(err.to_string()))?;
+
+        if file.tiles.is_empty() { return Err(LevelError::Empty); }
+
+        let width = file.tiles[0].chars().count();
+        let mut tiles = Vec::new();
+        for (row, line) in file.tiles.iter().enumerate() {
+            if line.chars().count() != width {
+                return Err(LevelError::RaggedRow { row, expected: width, found: line.chars().count() });
+            }
+
+            let mut tile_row = Vec::new();
+            for (column, c) in line.chars().enumerate() {
+                let tile_type = TileType::from_char(c)
+                    .ok_or(LevelError::UnknownTile { row, column, tile: c })?;
+                tile_row.push(tile_type);
+            }
+            tiles.push(tile_row);
+        }
+
+        if tiles.len() > MAX_LEVEL_SIZE || width > MAX_LEVEL_SIZE {
+            return Err(LevelError::TooBig { width, height: tiles.len() });
+        }
+
+        let plate_count = tiles.iter().flatten().filter(|tile| tile.pressure_plate_number().is_some()).count();
+        if plate_count > MAX_PRESSURE_PLATES {
+            return Err(LevelError::TooManyPlates(plate_count));
+        }
+
+        let mut teleporter_counts: HashMap<char, usize> = HashMap::new();
+        for tile in tiles.iter().flatten() {
+            if let TileType::Teleporter(link) = tile {
+                *teleporter_counts.entry(*link).or_default() += 1;
+            }
+        }
+        if let Some((&link, &count)) = teleporter_counts.iter().find(|(_, &count)| count != 2) {
+            return Err(LevelError::UnpairedTeleporter { link, count });
+        }
+
+        let switch_count = tiles.iter().flatten().filter(|tile| **tile == TileType::Switch).count();
+        if switch_count > MAX_SWITCHES {
+            return Err(LevelError::TooManySwitches(switch_count));
+        }
+
+        let crumbling_count = tiles.iter().flatten().filter(|tile| **tile == TileType::Crumbling).count();
+        if crumbling_count > MAX_CRUMBLING_FLOORS {
+            return Err(LevelError::TooManyCrumblingFloors(crumbling_count));
+        }
+
+        for (row, tile_row) in tiles.iter().enumerate() {
+            for (column, tile_type) in tile_row.iter().enumerate() {
+                let needs_link = matches!(tile_type, TileType::Door { .. } | TileType::Switch);
+                if needs_link && !file.links.contains_key(&(column, row)) {
+                    return Err(LevelError::Unlinked { column, row });
+                }
+            }
+        }
+        for &(column, row) in file.links.keys() {
+            let tile_type = tiles.get(row).and_then(|tile_row| tile_row.get(column));
+            let can_link = tile_type.is_some_and(|tile_type|
+                matches!(tile_type, TileType::Door { .. } | TileType::Switch) || tile_type.pressure_plate_number().is_some());
+            if !can_link {
+                return Err(LevelError::BadLink { column, row });
+            }
+        }
+
+        for &(column, row) in file.plate_modes.keys() {
+            let tile_type = tiles.get(row).and_then(|tile_row| tile_row.get(column));
+            if !tile_type.is_some_and(|tile_type| tile_type.pressure_plate_number().is_some()) {
+                return Err(LevelError::PlateModeNotOnPlate { column, row });
+            }
+        }
+
+        for (step, &(column, row)) in file.plate_order.iter().enumerate() {
+            let tile_type = tiles.get(row).and_then(|tile_row| tile_row.get(column));
+            let on_plate = tile_type.is_some_and(|tile_type| tile_type.pressure_plate_number().is_some());
+            if !on_plate || file.plate_order[..step].contains(&(column, row)) {
+                return Err(LevelError::BadPlateOrder { column, row });
+            }
+        }
+
+        for (row, tile_row) in tiles.iter().enumerate() {
+            for (column, tile_type) in tile_row.iter().enumerate() {
+                if *tile_type == TileType::FaceFilter && !file.face_filters.contains_key(&(column, row)) {
+                    return Err(LevelError::MissingFaceFilter { column, row });
+                }
+            }
+        }
+        for (&(column, row), filter) in file.face_filters.iter() {
+            let tile_type = tiles.get(row).and_then(|tile_row| tile_row.get(column));
+            if tile_type != Some(&TileType::FaceFilter) {
+                return Err(LevelError::FaceFilterNotOnFilterTile { column, row });
+            }
+            if matches!(filter, FaceFilter::Number(number) if !(1..=6).contains(number)) {
+                return Err(LevelError::BadFaceFilter { column, row });
+            }
+        }
+
+        for (row, tile_row) in tiles.iter().enumerate() {
+            for (column, tile_type) in tile_row.iter().enumerate() {
+                if *tile_type == TileType::Paint && !file.paints.contains_key(&(column, row)) {
+                    return Err(LevelError::MissingPaint { column, row });
+                }
+            }
+        }
+        for (&(column, row), &label) in file.paints.iter() {
+            let tile_type = tiles.get(row).and_then(|tile_row| tile_row.get(column));
+            if tile_type != Some(&TileType::Paint) {
+                return Err(LevelError::PaintNotOnPaintTile { column, row });
+            }
+            if label > MAX_FACE_LABEL {
+                return Err(LevelError::BadPaint { column, row, label });
+            }
+        }
+
+        if file.crates.len() > MAX_CRATES {
+            return Err(LevelError::TooManyCrates(file.crates.len()));
+        }
+        for (index, &(column, row)) in file.crates.iter().enumerate() {
+            let tile_type = tiles.get(row).and_then(|tile_row| tile_row.get(column));
+            let can_hold_crate = tile_type.is_some_and(|tile_type|
+                !matches!(tile_type, TileType::Wall | TileType::Door { open: false } | TileType::Pit));
+            let on_die = file.die_start == (column, row) || file.extra_dice.iter().any(|die| die.position == (column, row));
+            if !can_hold_crate || file.crates[..index].contains(&(column, row)) || on_die {
+                return Err(LevelError::BadCrate { column, row });
+            }
+        }
+
+        if let Some((column, row)) = find_conveyor_loop(&tiles) {
+            return Err(LevelError::ConveyorLoop { column, row });
+        }
+
+        if file.extra_dice.len() + 1 > MAX_DICE {
+            return Err(LevelError::TooManyDice(file.extra_dice.len() + 1));
+        }
+        let first_die = DieStart { position: file.die_start, orientation: file.die_orientation };
+        let dice: Vec<DieStart> = std::iter::once(first_die).chain(file.extra_dice.iter().copied()).collect();
+        for (index, die) in dice.iter().enumerate() {
+            let (column, row) = die.position;
+            if row >= tiles.len() || column >= width {
+                return Err(LevelError::DieOutOfBounds { column, row });
+            }
+            if matches!(tiles[row][column], TileType::Wall | TileType::Door { open: false } | TileType::Pit) {
+                return Err(LevelError::DieInWall { column, row });
+            }
+            if dice[..index].iter().any(|earlier| earlier.position == die.position) {
+                return Err(LevelError::DieOnDie { column, row });
+            }
+
+            if !die.orientation.is_valid() {
+                return Err(LevelError::InvalidOrientation(die.orientation));
+            }
+        }
+
+        Ok(Level {
+            tiles,
+            die_start: file.die_start,
+            die_orientation: file.die_orientation,
+            hint_limit: file.hint_limit,
+            links: file.links,
+            plate_modes: file.plate_modes,
+            plate_order: file.plate_order,
+            face_filters: file.face_filters,
+            paints: file.paints,
+            crates: file.crates,
+            extra_dice: file.extra_dice,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.tiles[0].len()
+    }
+
+    pub fn height(&self) -> usize {
+        self.tiles.len()
+    }
+
+    // Position of a tile in grid units, with the centre of the level at the origin
+    pub fn grid_position(&self, column: usize, row: usize) -> Vec2 {
+        Vec2::new(
+            column as f32 - (self.width() / 2) as f32,
+            (self.height() / 2) as f32 - row as f32)
+    }
+
+    // The (column, row) of the tile at a position in grid units, if it's inside the level
+    #[allow(clippy::needless_return)]
+    pub fn grid_index(&self, grid_position: Vec2) -> Option<(usize, usize)> {
+        let column = grid_position.x.round() + (self.width() / 2) as f32;
+        let row = (self.height() / 2) as f32 - grid_position.y.round();
+        if column < 0.0 || row < 0.0 { return None; }
+
+        let (column, row) = (column as usize, row as usize);
+        if column >= self.width() || row >= self.height() { return None; }
+        return Some((column, row));
+    }
+
+    // Every die in the level, starting with the one the player controls first
+    #[allow(clippy::needless_return)]
+    pub fn dice(&self) -> Vec<DieStart> {
+        let mut dice = vec![DieStart { position: self.die_start, orientation: self.die_orientation }];
+        dice.extend(self.extra_dice.iter().copied());
+        return dice;
+    }
+
+    pub fn plate_mode(&self, column: usize, row: usize) -> PlateMode {
+        self.plate_modes.get(&(column, row)).copied().unwrap_or_default()
+    }
+
+    // Where the plate at (column, row) comes in `plate_order`, if it's in there
+    pub fn plate_order_step(&self, column: usize, row: usize) -> Option<usize> {
+        self.plate_order.iter().position(|&position| position == (column, row))
+    }
+
+    // Maps the (column, row) of each teleporter to the (column, row) of its partner
+    #[allow(clippy::needless_return)]
+    pub fn teleporter_partners(&self) -> HashMap<(usize, usize), (usize, usize)> {
+        let mut first_of_pair: HashMap<char, (usize, usize)> = HashMap::new();
+        let mut partners = HashMap::new();
+        for (row, tile_row) in self.tiles.iter().enumerate() {
+            for (column, tile_type) in tile_row.iter().enumerate() {
+                let TileType::Teleporter(link) = tile_type else { continue; };
+                match first_of_pair.get(link) {
+                    Some(&partner) => {
+                        partners.insert(partner, (column, row));
+                        partners.insert((column, row), partner);
+                    },
+                    None => { first_of_pair.insert(*link, (column, row)); },
+                }
+            }
+        }
+        return partners;
+    }
+}
+
+// The first conveyor found that belts and ice would carry the die round in a circle from, which would carry it forever.
+// Doors might open and crates or other dice might move out of the way, so only walls are counted as stopping it.
+#[allow(clippy::needless_return)]
+fn find_conveyor_loop(tiles: &[Vec<TileType>]) -> Option<(usize, usize)> {
+    for (row, tile_row) in tiles.iter().enumerate() {
+        for (column, tile_type) in tile_row.iter().enumerate() {
+            let TileType::Conveyor(mut direction) = *tile_type else { continue; };
+            let mut visited = HashSet::new();
+            let mut position = (column, row);
+            loop {
+                // Belts turn the die their way, ice keeps it going the way it was already
+                match tiles[position.1][position.0] {
+                    TileType::Conveyor(belt_direction) => direction = belt_direction,
+                    TileType::Ice => {},
+                    _ => break,
+                }
+                if !visited.insert((position, direction)) { return Some((column, row)); }
+
+                let (column, row) = position;
+                let next = match direction {
+                    Direction::Up => row.checked_sub(1).map(|row| (column, row)),
+                    Direction::Down => Some((column, row + 1)),
+                    Direction::Left => column.checked_sub(1).map(|column| (column, row)),
+                    Direction::Right => Some((column + 1, row)),
+                };
+                let Some(next) = next else { break; };
+                if next.1 >= tiles.len() || next.0 >= tiles[next.1].len() || tiles[next.1][next.0] == TileType::Wall { break; }
+                position = next;
+            }
+        }
+    }
+    return None;
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Syntax(String),
+    Empty,
+    RaggedRow { row: usize, expected: usize, found: usize },
+    UnknownTile { row: usize, column: usize, tile: char },
+    TooBig { width: usize, height: usize },
+    TooManyPlates(usize),
+    TooManySwitches(usize),
+    TooManyCrumblingFloors(usize),
+    UnpairedTeleporter { link: char, count: usize },
+    Unlinked { column: usize, row: usize },
+    BadLink { column: usize, row: usize },
+    PlateModeNotOnPlate { column: usize, row: usize },
+    BadPlateOrder { column: usize, row: usize },
+    MissingFaceFilter { column: usize, row: usize },
+    FaceFilterNotOnFilterTile { column: usize, row: usize },
+    BadFaceFilter { column: usize, row: usize },
+    MissingPaint { column: usize, row: usize },
+    PaintNotOnPaintTile { column: usize, row: usize },
+    BadPaint { column: usize, row: usize, label: usize },
+    TooManyCrates(usize),
+    BadCrate { column: usize, row: usize },
+    ConveyorLoop { column: usize, row: usize },
+    DieOutOfBounds { column: usize, row: usize },
+    DieInWall { column: usize, row: usize },
+    DieOnDie { column: usize, row: usize },
+    TooManyDice(usize),
+    InvalidOrientation(DieOrientation),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Syntax(message) => write!(f, "level is not valid RON: {}", message),
+            LevelError::Empty => write!(f, "level has no rows of tiles"),
+            LevelError::RaggedRow { row, expected, found } =>
+                write!(f, "row {} has {} tiles but the first row has {}", row, found, expected),
+            LevelError::UnknownTile { row, column, tile } =>
+                write!(f, "unknown tile '{}' at column {}, row {}", tile, column, row),
+            LevelError::TooBig { width, height } =>
+                write!(f, "level is {} by {} tiles but at most {} by {} are supported", width, height, MAX_LEVEL_SIZE, MAX_LEVEL_SIZE),
+            LevelError::TooManyPlates(count) =>
+                write!(f, "level has {} pressure plates but at most {} are supported", count, MAX_PRESSURE_PLATES),
+            LevelError::TooManySwitches(count) =>
+                write!(f, "level has {} switches but at most {} are supported", count, MAX_SWITCHES),
+            LevelError::TooManyCrumblingFloors(count) =>
+                write!(f, "level has {} crumbling floors but at most {} are supported", count, MAX_CRUMBLING_FLOORS),
+            LevelError::Unlinked { column, row } =>
+                write!(f, "door or switch at column {}, row {} has no link ID", column, row),
+            LevelError::BadLink { column, row } =>
+                write!(f, "link at column {}, row {} is not on a door, switch or pressure plate", column, row),
+            LevelError::PlateModeNotOnPlate { column, row } =>
+                write!(f, "plate mode given for column {}, row {} but there is no pressure plate there", column, row),
+            LevelError::BadPlateOrder { column, row } =>
+                write!(f, "plate order lists column {}, row {} but there is no pressure plate there, or it's listed twice", column, row),
+            LevelError::MissingFaceFilter { column, row } =>
+                write!(f, "face filter at column {}, row {} doesn't say which numbers it lets through", column, row),
+            LevelError::FaceFilterNotOnFilterTile { column, row } =>
+                write!(f, "face filter given for column {}, row {} but there is no face filter tile there", column, row),
+            LevelError::BadFaceFilter { column, row } =>
+                write!(f, "face filter at column {}, row {} lets through a number that isn't on the die", column, row),
+            LevelError::MissingPaint { column, row } =>
+                write!(f, "paint at column {}, row {} doesn't say which label it paints", column, row),
+            LevelError::PaintNotOnPaintTile { column, row } =>
+                write!(f, "paint given for column {}, row {} but there is no paint tile there", column, row),
+            LevelError::BadPaint { column, row, label } =>
+                write!(f, "paint at column {}, row {} paints {} but labels only go up to {}", column, row, label, MAX_FACE_LABEL),
+            LevelError::TooManyCrates(count) =>
+                write!(f, "level has {} crates but at most {} are supported", count, MAX_CRATES),
+            LevelError::BadCrate { column, row } =>
+                write!(f, "crate at column {}, row {} is outside the level, in a wall, closed door or pit, on the die or on another crate", column, row),
+            LevelError::UnpairedTeleporter { link, count } =>
+                write!(f, "teleporter '{}' appears {} times but teleporters must come in pairs", link, count),
+            LevelError::ConveyorLoop { column, row } =>
+                write!(f, "conveyor at column {}, row {} can carry the die round in a loop", column, row),
+            LevelError::DieOutOfBounds { column, row } =>
+                write!(f, "die start (column {}, row {}) is outside the level", column, row),
+            LevelError::DieInWall { column, row } =>
+                write!(f, "die start (column {}, row {}) is inside a wall, closed door or pit", column, row),
+            LevelError::DieOnDie { column, row } =>
+                write!(f, "more than one die starts at column {}, row {}", column, row),
+            LevelError::TooManyDice(count) =>
+                write!(f, "level has {} dice but at most {} are supported", count, MAX_DICE),
+            LevelError::InvalidOrientation(orientation) =>
+                write!(f, "die orientation {:?} must use each of 1-6 once with opposite faces summing to 7", orientation),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+#[derive(Default)]
+pub struct LevelLoader;
+
+impl AssetLoader for LevelLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let level = Level::from_ron(bytes).map_err(|err| {
+                log::error!("Failed to load level {}: {}", load_context.path().display(), err);
+                err
+            })?;
+            load_context.set_default_asset(LoadedAsset::new(level));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTRA_DIE_ORIENTATION: &str = "(face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6)";
+
+    #[test]
+    fn tiles_are_read_row_by_row() {
+        let level = Level::from_ron(b"(tiles: [\"####\", \"#.3#\", \"####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))").unwrap();
+        assert_eq!(level.tiles[1], vec![TileType::Wall, TileType::Floor, TileType::PressurePlate3, TileType::Wall]);
+        assert_eq!(level.dice()[0].position, (1, 1));
+    }
+
+    #[test]
+    fn pressure_plates_only_go_up_to_six() {
+        for number in 1..=6 {
+            assert_eq!(TileType::pressure_plate(number).and_then(|plate| plate.pressure_plate_number()), Some(number));
+        }
+        assert_eq!(TileType::pressure_plate(0), None);
+        assert_eq!(TileType::pressure_plate(7), None);
+    }
+
+    #[test]
+    fn rows_have_to_be_known_tiles_of_the_same_width() {
+        let result = Level::from_ron(b"(tiles: [\"####\", \"#..#\", \"###\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::RaggedRow { row: 2, expected: 4, found: 3 })));
+
+        let result = Level::from_ron(b"(tiles: [\"####\", \"#.!#\", \"####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::UnknownTile { row: 1, column: 2, tile: '!' })));
+
+        let result = Level::from_ron(b"(tiles: [], die_start: (0, 0), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::Empty)));
+    }
+
+    #[test]
+    fn levels_are_at_most_max_size_across() {
+        let ron = format!("(tiles: [\"{0}\", \"{0}\", \"{0}\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))", "#".repeat(MAX_LEVEL_SIZE + 1));
+        let result = Level::from_ron(ron.as_bytes());
+        assert!(matches!(result, Err(LevelError::TooBig { width, height: 3 }) if width == MAX_LEVEL_SIZE + 1));
+    }
+
+    #[test]
+    fn die_has_to_start_inside_on_the_floor() {
+        let result = Level::from_ron(b"(tiles: [\"####\", \"#..#\", \"####\"], die_start: (0, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::DieInWall { column: 0, row: 1 })));
+
+        let result = Level::from_ron(b"(tiles: [\"####\", \"#..#\", \"####\"], die_start: (4, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::DieOutOfBounds { column: 4, row: 1 })));
+    }
+
+    #[test]
+    fn teleporters_must_be_paired() {
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#.a.#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::UnpairedTeleporter { link: 'a', count: 1 })));
+    }
+
+    #[test]
+    fn conveyor_loops_are_rejected() {
+        let result = Level::from_ron(b"(tiles: [\"######\", \"#.><.#\", \"######\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::ConveyorLoop { column: 2, row: 1 })));
+    }
+
+    #[test]
+    fn conveyor_loops_through_ice_are_rejected() {
+        let result = Level::from_ron(b"(tiles: [\"########\", \"#.>~~<.#\", \"########\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::ConveyorLoop { column: 2, row: 1 })));
+
+        let result = Level::from_ron(b"(tiles: [\"######\", \"#.>~~#\", \"######\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn doors_and_switches_need_links() {
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#.|.#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::Unlinked { column: 2, row: 1 })));
+
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#...#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), links: {(2, 1): 0})");
+        assert!(matches!(result, Err(LevelError::BadLink { column: 2, row: 1 })));
+    }
+
+    #[test]
+    fn plate_order_must_list_plates() {
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#.3.#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), plate_order: [(2, 1), (3, 1)])");
+        assert!(matches!(result, Err(LevelError::BadPlateOrder { column: 3, row: 1 })));
+    }
+
+    #[test]
+    fn face_filters_need_a_filter() {
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#.?.#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::MissingFaceFilter { column: 2, row: 1 })));
+
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#...#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), face_filters: {(2, 1): Odd})");
+        assert!(matches!(result, Err(LevelError::FaceFilterNotOnFilterTile { column: 2, row: 1 })));
+
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#.?.#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), face_filters: {(2, 1): Number(7)})");
+        assert!(matches!(result, Err(LevelError::BadFaceFilter { column: 2, row: 1 })));
+    }
+
+    #[test]
+    fn paint_needs_a_label_on_the_die() {
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#.@.#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6))");
+        assert!(matches!(result, Err(LevelError::MissingPaint { column: 2, row: 1 })));
+
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#.@.#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), paints: {(2, 1): 100})");
+        assert!(matches!(result, Err(LevelError::BadPaint { column: 2, row: 1, label: 100 })));
+
+        // Labels past six don't need pips, they're printed on the die
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#.@.#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), paints: {(2, 1): 12})");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn crates_have_to_be_somewhere_free() {
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#...#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), crates: [(2, 0)])");
+        assert!(matches!(result, Err(LevelError::BadCrate { column: 2, row: 0 })));
+
+        let result = Level::from_ron(b"(tiles: [\"#####\", \"#...#\", \"#####\"], die_start: (1, 1), die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), crates: [(1, 1)])");
+        assert!(matches!(result, Err(LevelError::BadCrate { column: 1, row: 1 })));
+    }
+
+    #[test]
+    fn dice_have_to_start_apart_and_be_few_enough() {
+        let ron = format!(
+            "(tiles: [\"#####\", \"#...#\", \"#####\"], die_start: (1, 1), die_orientation: {0}, extra_dice: [(position: (1, 1), orientation: {0})])",
+            EXTRA_DIE_ORIENTATION);
+        assert!(matches!(Level::from_ron(ron.as_bytes()), Err(LevelError::DieOnDie { column: 1, row: 1 })));
+
+        let extra_dice: Vec<String> = (1..=4)
+            .map(|column| format!("(position: ({}, 1), orientation: {})", column, EXTRA_DIE_ORIENTATION))
+            .collect();
+        let ron = format!(
+            "(tiles: [\"#######\", \"#.....#\", \"#######\"], die_start: (5, 1), die_orientation: {}, extra_dice: [{}])",
+            EXTRA_DIE_ORIENTATION,
+            extra_dice.join(","));
+        assert!(matches!(Level::from_ron(ron.as_bytes()), Err(LevelError::TooManyDice(5))));
+    }
+}
@@ -3,16 +3,25 @@ use bevy::{
     log::LogPlugin, 
 };
 
+use level::PlateMode;
+use world_plugin::{Campaign, CurrentLevel, SpawnedLevel};
+
 mod die_plugin;
+mod die_orientation;
 mod world_plugin;
+mod level;
+mod solver;
+mod generator;
+mod daily;
 mod direction;
+mod key_bindings;
 mod controls_plugin;
+mod hint_plugin;
+mod hud_plugin;
 mod title_screen_plugin;
 mod victory_screen_plugin;
-
-use die_plugin::Die;
-use die_plugin::DIE_STARTING_POSITION;
-use die_plugin::get_die_face_sprite_index;
+mod failed_screen_plugin;
+mod settings_plugin;
 
 fn main() {
     // When building for WASM, print panics to the browser console
@@ -34,18 +43,18 @@ fn main() {
         .add_plugins((
             title_screen_plugin::TitleScreenPlugin,
             victory_screen_plugin::VictoryScreenPlugin,
+            failed_screen_plugin::FailedScreenPlugin,
             world_plugin::WorldPlugin,
             die_plugin::DiePlugin,
-            controls_plugin::ControlsPlugin
+            controls_plugin::ControlsPlugin,
+            hint_plugin::HintPlugin,
+            hud_plugin::HudPlugin,
+            settings_plugin::SettingsPlugin,
         ))
         .add_systems(
             Update,
             check_for_victory.run_if(in_state(GameState::Playing))
         )
-        .add_systems(
-            Update,
-            reset_game.run_if(in_state(GameState::Finished))
-        )
         .insert_resource(FixedTime::new_from_secs(0.03))
         .run();
 }
@@ -73,38 +82,61 @@ fn setup(
 
 
 fn check_for_victory(
+    campaign: Res<Campaign>,
+    current_level: Res<CurrentLevel>,
+    spawned_level: Res<SpawnedLevel>,
     pressure_plates_query: Query<& PressurePlate>,
     mut state: ResMut<NextState<GameState>>,
 ) {
-    let mut all_plates_active = true;
+    // Until the level has been spawned, the plates are the last level's (or there aren't any yet). A level with no
+    // plates at all is won straight away, like the solver says.
+    if spawned_level.0.as_ref() != Some(campaign.handle(&current_level)) { return; }
+
+    let mut all_plates_done = true;
     for pressure_plate in pressure_plates_query.iter() {
-        all_plates_active = all_plates_active && pressure_plate.activated;
+        all_plates_done = all_plates_done && pressure_plate.is_done();
     }
 
-    if all_plates_active {
+    if all_plates_done {
         state.set(GameState::Finished);
     }
 }
 
-fn reset_game(
-    mut pressure_plates_query: Query<&mut PressurePlate>,
-    mut die_query: Query<(&mut Transform, &mut Die, &mut TextureAtlasSprite)>,
-) {
-    for mut pressure_plate in pressure_plates_query.iter_mut() { pressure_plate.activated = false; }
-    let (mut die_transform, mut die, mut sprite) = die_query.single_mut();
-
-    die_transform.translation = DIE_STARTING_POSITION;
-    die.destination_translation = DIE_STARTING_POSITION;
-    sprite.index = get_die_face_sprite_index(die.face_number);
-}
-
 #[derive(Component)]
 struct Collider;
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 struct PressurePlate{
     activated: bool,
+    completed: bool, // Has been activated at some point, even if it has let go since
     number: usize,
+    mode: PlateMode,
+    order_step: Option<usize>, // Where it comes in the level's plate order, if it has one
+    owner: Option<usize>, // The player who activated it first in two-player mode
+}
+
+impl PressurePlate {
+    fn new(number: usize, mode: PlateMode, order_step: Option<usize>) -> PressurePlate {
+        PressurePlate { activated: false, completed: false, number, mode, order_step, owner: None }
+    }
+
+    // How many plates each player activated first in a race
+    fn plates_won<'a>(pressure_plates: impl Iterator<Item = &'a PressurePlate>) -> [usize; 2] {
+        let mut plates_won = [0; 2];
+        for owner in pressure_plates.filter_map(|pressure_plate| pressure_plate.owner) {
+            plates_won[owner] += 1;
+        }
+        plates_won
+    }
+
+    // Whether this plate is happy for the level to be won
+    #[allow(clippy::needless_return)]
+    fn is_done(&self) -> bool {
+        match self.mode {
+            PlateMode::Latching | PlateMode::Momentary => return self.completed,
+            PlateMode::Held => return self.activated,
+        }
+    }
 }
 
 #[derive(Hash, Debug, PartialEq, Eq, Clone, Copy, Default, States)]
@@ -113,6 +145,8 @@ enum GameState {
     MainMenu,
     Playing,
     Finished,
+    Failed, // The die fell off the level, so it has to be restarted
+    Settings, // Rebinding keys, with the game paused
 }
 
 // Globals
@@ -0,0 +1,218 @@
+use bevy::prelude::*;
+use crate::{GameState, key_bindings::{Action, ACTIONS, KeyBindings, PRESETS}};
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(KeyBindings::load())
+            .init_resource::<SettingsMenu>()
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                open_settings.run_if(in_state(GameState::Playing).or_else(in_state(GameState::MainMenu)))
+            )
+            .add_systems(
+                OnEnter(GameState::Settings),
+                show
+            )
+            .add_systems(
+                Update,
+                update.run_if(in_state(GameState::Settings))
+            )
+            .add_systems(
+                OnExit(GameState::Settings),
+                hide
+            );
+    }
+}
+#[derive(Component)]
+struct SettingsUi;
+
+// The menu's own keys can't be rebound, so there's always a way back out of it
+const PREVIOUS_KEY: KeyCode = KeyCode::Up;
+const NEXT_KEY: KeyCode = KeyCode::Down;
+const REBIND_KEY: KeyCode = KeyCode::Return;
+const CLOSE_KEY: KeyCode = KeyCode::Escape;
+const PRESET_KEYS: [KeyCode; 3] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+
+#[derive(Resource, Default)]
+struct SettingsMenu {
+    return_to: GameState, // Where the menu was opened from, which carries on once it's closed
+    selected: usize, // Index into `ACTIONS`
+    rebinding: bool, // Waiting for the key to bind to the selected action
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands
+        .spawn(TextBundle {
+            style: Style {
+                align_self: AlignSelf::FlexEnd,
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(5.0),
+                right: Val::Px(15.0),
+                ..default()
+            },
+            text: Text::from_sections([
+                TextSection::new(
+                    "Key bindings\n",
+                    TextStyle {
+                        font: asset_server.load("fonts/FreeSans.ttf"),
+                        font_size: 60.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FreeSans.ttf"),
+                        font_size: 26.0,
+                        color: Color::WHITE,
+                    },
+                ),
+            ]).with_alignment(TextAlignment::Left),
+            visibility: Visibility::Hidden,
+            ..default()
+        })
+        .insert(SettingsUi);
+}
+
+// Pausing brings up the menu, from the title screen or part way through a level
+fn open_settings(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    current_state: Res<State<GameState>>,
+    mut menu: ResMut<SettingsMenu>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    if !key_bindings.just_pressed(&keyboard_input, Action::Pause) { return; }
+
+    *menu = SettingsMenu { return_to: *current_state.get(), ..default() };
+    state.set(GameState::Settings);
+}
+
+fn show(
+    key_bindings: Res<KeyBindings>,
+    menu: Res<SettingsMenu>,
+    mut query: Query<
+        (&mut Visibility, &mut Text),
+        With<SettingsUi>>,
+) {
+    for (mut visibility, mut text) in query.iter_mut() {
+        *visibility = Visibility::Visible;
+        text.sections[1].value = menu_text(&key_bindings, &menu);
+    }
+}
+
+fn update(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut key_bindings: ResMut<KeyBindings>,
+    mut menu: ResMut<SettingsMenu>,
+    mut query: Query<&mut Text, With<SettingsUi>>,
+    mut state: ResMut<NextState<GameState>>,
+) {
+    if menu.rebinding {
+        // Whatever's pressed next is the new key, unless it's backing out
+        let Some(&key) = keyboard_input.get_just_pressed().next() else { return; };
+        menu.rebinding = false;
+        if key != CLOSE_KEY {
+            match rebind(&key_bindings, ACTIONS[menu.selected], key) {
+                Some(rebound) => {
+                    *key_bindings = rebound;
+                    key_bindings.save();
+                }
+                None => log::info!("Pause can't go on {:?}, the settings menu needs it", key),
+            }
+        }
+    }
+    else if keyboard_input.just_pressed(CLOSE_KEY) || key_bindings.just_pressed(&keyboard_input, Action::Pause) {
+        state.set(menu.return_to);
+        return;
+    }
+    else if keyboard_input.just_pressed(PREVIOUS_KEY) {
+        menu.selected = (menu.selected + ACTIONS.len() - 1) % ACTIONS.len();
+    }
+    else if keyboard_input.just_pressed(NEXT_KEY) {
+        menu.selected = (menu.selected + 1) % ACTIONS.len();
+    }
+    else if keyboard_input.just_pressed(REBIND_KEY) {
+        menu.rebinding = true;
+    }
+    else if let Some(index) = PRESET_KEYS.iter().position(|key| keyboard_input.just_pressed(*key)) {
+        *key_bindings = KeyBindings::preset(PRESETS[index]);
+        key_bindings.save();
+    }
+    else {
+        return;
+    }
+
+    for mut text in query.iter_mut() {
+        text.sections[1].value = menu_text(&key_bindings, &menu);
+    }
+}
+
+// The bindings with the action on the key, or None if that would leave Pause on one of the menu's own keys, as
+// pressing it would close the menu rather than do what the key's for. Binding an action can swap Pause's key too.
+#[allow(clippy::needless_return)]
+fn rebind(key_bindings: &KeyBindings, action: Action, key: KeyCode) -> Option<KeyBindings> {
+    let mut rebound = key_bindings.clone();
+    rebound.bind(action, key);
+    let pause = rebound.key(Action::Pause);
+    if [PREVIOUS_KEY, NEXT_KEY, REBIND_KEY].contains(&pause) || PRESET_KEYS.contains(&pause) {
+        return None;
+    }
+    return Some(rebound);
+}
+
+#[allow(clippy::needless_return)]
+fn menu_text(key_bindings: &KeyBindings, menu: &SettingsMenu) -> String {
+    let mut text = String::new();
+    for (index, action) in ACTIONS.iter().enumerate() {
+        let key = if menu.rebinding && index == menu.selected { "press a key...".to_string() } else { format!("{:?}", key_bindings.key(*action)) };
+        let cursor = if index == menu.selected { "> " } else { "   " };
+        text += &format!("{}{}: {}\n", cursor, action.name(), key);
+    }
+
+    text += "\nUp/Down: choose    Enter: rebind    Esc: back\n";
+    let presets: Vec<String> = PRESETS
+        .iter()
+        .enumerate()
+        .map(|(index, preset)| format!("{}: {}", index + 1, preset.name()))
+        .collect();
+    text += &presets.join("    ");
+    return text;
+}
+
+fn hide(
+    mut query: Query<
+        &mut Visibility,
+        With<SettingsUi>>,
+) {
+    for mut visibility in query.iter_mut() {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_stays_off_the_menu_keys() {
+        let bindings = KeyBindings::default();
+        for key in [PREVIOUS_KEY, NEXT_KEY, REBIND_KEY, PRESET_KEYS[0], PRESET_KEYS[1], PRESET_KEYS[2]] {
+            assert_eq!(rebind(&bindings, Action::Pause, key), None, "{:?}", key);
+        }
+
+        // Taking Pause's key would swap it onto Up
+        assert_eq!(rebind(&bindings, Action::MoveUp, KeyCode::Escape), None);
+
+        let rebound = rebind(&bindings, Action::Pause, KeyCode::P).unwrap();
+        assert_eq!(rebound.key(Action::Pause), KeyCode::P);
+        assert_eq!(rebind(&bindings, Action::Undo, KeyCode::Key1).unwrap().key(Action::Undo), KeyCode::Key1);
+    }
+}
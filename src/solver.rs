@@ -0,0 +1,1159 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    die_orientation::DieOrientation,
+    direction::Direction,
+    level::{FaceFilter, Level, PlateMode, TileType, MAX_CRATES, MAX_DICE},
+};
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+// Everything that can change while playing a level
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PuzzleState {
+    pub position: (usize, usize), // (column, row) in the level's tile grid of the die being rolled
+    pub orientation: DieOrientation,
+    pub other_dice: OtherDice, // See `sorted_dice`
+    pub activated_plates: u64, // Bit n is set while plate n (see `Puzzle::plate_index`) is activated
+    pub completed_plates: u64, // Bit n is set once plate n has been activated at some point
+    pub plate_order_next: usize, // How many plates of the level's plate order have been done
+    pub switches_on: u64, // Bit n is set while switch n (see `Puzzle::switch_index`) is on
+    pub crumbled: u64, // Bit n is set once crumbling floor n (see `Puzzle::crumbling_index`) has become a pit
+    pub crates: [(usize, usize); MAX_CRATES], // See `sorted_crates`
+}
+
+// Fills the slots past the last crate
+pub const NO_CRATE: (usize, usize) = (usize::MAX, usize::MAX);
+
+// Crate positions sorted, so states that only differ by which crate is where count as the same
+#[allow(clippy::needless_return)]
+pub fn sorted_crates(positions: &[(usize, usize)]) -> [(usize, usize); MAX_CRATES] {
+    let mut crates = [NO_CRATE; MAX_CRATES];
+    crates[..positions.len()].copy_from_slice(positions);
+    crates.sort();
+    return crates;
+}
+
+pub type OtherDice = [Option<((usize, usize), DieOrientation)>; MAX_DICE - 1];
+
+// The positions and orientations of the dice not being rolled, sorted like `sorted_crates`
+#[allow(clippy::needless_return)]
+pub fn sorted_dice(dice: &[((usize, usize), DieOrientation)]) -> OtherDice {
+    let mut other_dice = [None; MAX_DICE - 1];
+    for (slot, die) in other_dice.iter_mut().zip(dice) {
+        *slot = Some(*die);
+    }
+    other_dice.sort_by_key(|die| die.map(|(position, _)| position));
+    return other_dice;
+}
+
+// A `PuzzleState` squeezed down for the search, which keeps every state it reaches. Positions fit in a u8 as levels
+// are at most `MAX_LEVEL_SIZE` tiles across.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct PackedState {
+    position: (u8, u8),
+    orientation: [u8; 6],
+    pieces: u32, // Index of the crates and other dice in `Pieces`
+    plate_order_next: u8,
+    activated_plates: u64,
+    completed_plates: u64,
+    switches_on: u64,
+    crumbled: u64,
+}
+
+// Each arrangement of crates and dice not being rolled that the search has come across. Most levels have few or none of
+// them, and they move far less often than the die being rolled, so states share them rather than each having a copy.
+#[derive(Default)]
+struct Pieces {
+    arrangements: Vec<(OtherDice, [(usize, usize); MAX_CRATES])>,
+    indices: HashMap<(OtherDice, [(usize, usize); MAX_CRATES]), u32>,
+}
+
+impl Pieces {
+    // `likely` is the arrangement to check first, usually the one before the roll as most rolls don't push a crate
+    fn pack(&mut self, state: &PuzzleState, likely: u32) -> PackedState {
+        let arrangement = (state.other_dice, state.crates);
+        let pieces = if self.arrangements.get(likely as usize) == Some(&arrangement) {
+            likely
+        } else {
+            *self.indices.entry(arrangement).or_insert_with(|| {
+                self.arrangements.push(arrangement);
+                (self.arrangements.len() - 1) as u32
+            })
+        };
+        PackedState {
+            position: (state.position.0 as u8, state.position.1 as u8),
+            orientation: pack_orientation(&state.orientation),
+            pieces,
+            plate_order_next: state.plate_order_next as u8,
+            activated_plates: state.activated_plates,
+            completed_plates: state.completed_plates,
+            switches_on: state.switches_on,
+            crumbled: state.crumbled,
+        }
+    }
+
+    fn unpack(&self, packed: &PackedState) -> PuzzleState {
+        let (other_dice, crates) = self.arrangements[packed.pieces as usize];
+        PuzzleState {
+            position: (packed.position.0 as usize, packed.position.1 as usize),
+            orientation: unpack_orientation(packed.orientation),
+            other_dice,
+            activated_plates: packed.activated_plates,
+            completed_plates: packed.completed_plates,
+            plate_order_next: packed.plate_order_next as usize,
+            switches_on: packed.switches_on,
+            crumbled: packed.crumbled,
+            crates,
+        }
+    }
+}
+
+// Labels only go up to `MAX_FACE_LABEL`, so they fit in a u8 too
+fn pack_orientation(orientation: &DieOrientation) -> [u8; 6] {
+    [orientation.face, orientation.top, orientation.right, orientation.bottom, orientation.left, orientation.hidden].map(|label| label as u8)
+}
+
+fn unpack_orientation(labels: [u8; 6]) -> DieOrientation {
+    let [face, top, right, bottom, left, hidden] = labels.map(|label| label as usize);
+    DieOrientation { face, top, right, bottom, left, hidden }
+}
+
+// One roll of a solution
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Roll {
+    pub die: (usize, usize), // Where the die to roll is
+    pub direction: Direction,
+}
+
+// The unchanging parts of a level, with the rules for moving the die around it
+pub struct Puzzle {
+    tiles: Vec<Vec<TileType>>,
+    plate_indices: HashMap<(usize, usize), usize>,
+    latching_plates: u64, // Bits of the plates that stay activated after the die leaves
+    held_plates: u64, // Bits of the plates that have to be activated at the end
+    ordered_plates: u64, // Bits of the plates in the level's plate order
+    plate_order_steps: HashMap<(usize, usize), usize>,
+    switch_indices: HashMap<(usize, usize), usize>,
+    crumbling_indices: HashMap<(usize, usize), usize>,
+    teleporter_partners: HashMap<(usize, usize), (usize, usize)>,
+    links: HashMap<(usize, usize), usize>,
+    linked_bits: HashMap<usize, (u64, u64)>, // The plate and switch bits that swap the doors with each link ID
+    face_filters: HashMap<(usize, usize), FaceFilter>,
+    paints: HashMap<(usize, usize), usize>,
+    start: PuzzleState, // Before the plates the dice and crates start out on are pressed
+}
+
+impl Puzzle {
+    pub fn new(level: &Level) -> Puzzle {
+        let mut plate_indices = HashMap::new();
+        let mut latching_plates = 0;
+        let mut held_plates = 0;
+        let mut ordered_plates = 0;
+        let mut switch_indices = HashMap::new();
+        let mut crumbling_indices = HashMap::new();
+        let mut linked_bits: HashMap<usize, (u64, u64)> = HashMap::new();
+        for (row, tile_row) in level.tiles.iter().enumerate() {
+            for (column, tile_type) in tile_row.iter().enumerate() {
+                let link = level.links.get(&(column, row)).copied();
+                if tile_type.pressure_plate_number().is_some() {
+                    if let Some(link) = link {
+                        linked_bits.entry(link).or_default().0 |= 1 << plate_indices.len();
+                    }
+                    match level.plate_mode(column, row) {
+                        PlateMode::Latching => latching_plates |= 1 << plate_indices.len(),
+                        PlateMode::Momentary => {},
+                        PlateMode::Held => held_plates |= 1 << plate_indices.len(),
+                    }
+                    if level.plate_order_step(column, row).is_some() {
+                        ordered_plates |= 1 << plate_indices.len();
+                    }
+                    plate_indices.insert((column, row), plate_indices.len());
+                }
+                if *tile_type == TileType::Switch {
+                    if let Some(link) = link {
+                        linked_bits.entry(link).or_default().1 |= 1 << switch_indices.len();
+                    }
+                    switch_indices.insert((column, row), switch_indices.len());
+                }
+                if *tile_type == TileType::Crumbling {
+                    crumbling_indices.insert((column, row), crumbling_indices.len());
+                }
+            }
+        }
+
+        let other_dice: Vec<_> = level.extra_dice.iter().map(|die| (die.position, die.orientation)).collect();
+        Puzzle {
+            tiles: level.tiles.clone(),
+            plate_indices,
+            latching_plates,
+            held_plates,
+            ordered_plates,
+            plate_order_steps: level.plate_order.iter().enumerate().map(|(step, &position)| (position, step)).collect(),
+            switch_indices,
+            crumbling_indices,
+            teleporter_partners: level.teleporter_partners(),
+            links: level.links.clone(),
+            linked_bits,
+            face_filters: level.face_filters.clone(),
+            paints: level.paints.clone(),
+            start: PuzzleState {
+                position: level.die_start,
+                orientation: level.die_orientation,
+                other_dice: sorted_dice(&other_dice),
+                activated_plates: 0,
+                completed_plates: 0,
+                plate_order_next: 0,
+                switches_on: 0,
+                crumbled: 0,
+                crates: sorted_crates(&level.crates),
+            },
+        }
+    }
+
+    // Dice and crates that start out on plates press them straight away, like they do in the game
+    pub fn start_state(&self) -> PuzzleState {
+        let mut state = self.start;
+        let (column, row) = state.position;
+        if self.tiles[row][column].pressure_plate_number() == Some(state.orientation.face) {
+            self.press_plate(&mut state, (column, row));
+        }
+        self.press_resting_plates(&mut state);
+        state
+    }
+
+    // Plates are numbered in reading order, left to right then top to bottom
+    pub fn plate_index(&self, position: (usize, usize)) -> Option<usize> {
+        self.plate_indices.get(&position).copied()
+    }
+
+    // Switches are numbered the same way as plates
+    pub fn switch_index(&self, position: (usize, usize)) -> Option<usize> {
+        self.switch_indices.get(&position).copied()
+    }
+
+    // And so are crumbling floors
+    pub fn crumbling_index(&self, position: (usize, usize)) -> Option<usize> {
+        self.crumbling_indices.get(&position).copied()
+    }
+
+    // Every plate has been activated, and the held ones still are
+    pub fn is_solved(&self, state: &PuzzleState) -> bool {
+        state.completed_plates.count_ones() as usize == self.plate_indices.len() &&
+            state.activated_plates & self.held_plates == self.held_plates
+    }
+
+    // The state after rolling the die one tile in `direction`, and wherever the tiles carry it after that.
+    // None if something is in the way.
+    #[allow(clippy::needless_return)]
+    pub fn step(&self, state: &PuzzleState, direction: Direction) -> Option<PuzzleState> {
+        let mut state = *state;
+
+        // Rolling into a crate pushes it along one tile, if there's room for it
+        let ahead = self.neighbour(state.position, direction)?;
+        if let Some(index) = state.crates.iter().position(|&position| position == ahead) {
+            let pushed_to = self.neighbour(ahead, direction)?;
+            if !self.can_take_crate(&state, pushed_to) { return None; }
+            state.crates[index] = pushed_to;
+            state.crates.sort();
+        }
+
+        let mut orientation = state.orientation.roll(direction);
+        let mut position = self.open_neighbour(&state, state.position, direction, orientation.face)?;
+        let mut direction = direction;
+
+        // Crumbling floor gives way as soon as the die leaves it
+        if let Some(index) = self.crumbling_index(state.position) {
+            state.crumbled |= 1 << index;
+        }
+        let state = &state;
+
+        // Level loading rules out belts and ice that loop, but never carry the die forever regardless
+        for _ in 0..self.tiles.len() * self.tiles[0].len() {
+            match self.tiles[position.1][position.0] {
+                // Ice slides the die on without rolling it, until it is blocked or reaches a tile that isn't ice
+                TileType::Ice => {
+                    while self.tiles[position.1][position.0] == TileType::Ice {
+                        let Some(next) = self.open_neighbour(state, position, direction, orientation.face) else { break; };
+                        position = next;
+                    }
+                    if self.tiles[position.1][position.0] == TileType::Ice { return Some(self.land(state, position, orientation)); }
+                },
+                // Teleporters send the die on to their partner, but arriving there doesn't send it back
+                TileType::Teleporter(_) => {
+                    let partner = self.teleporter_partners[&position];
+                    if state.crates.contains(&partner) || self.has_other_die(state, partner) {
+                        return Some(self.land(state, position, orientation));
+                    }
+                    return Some(self.land(state, partner, orientation));
+                },
+                TileType::Conveyor(belt_direction) => {
+                    let rolled = orientation.roll(belt_direction);
+                    let Some(next) = self.open_neighbour(state, position, belt_direction, rolled.face) else {
+                        return Some(self.land(state, position, orientation));
+                    };
+                    position = next;
+                    orientation = rolled;
+                    direction = belt_direction;
+                },
+                // Falling in loses the level, so it's never a way forward
+                TileType::Pit => return None,
+                TileType::Crumbling if self.is_crumbled(state, position) => return None,
+                TileType::Rotator { clockwise } => {
+                    return Some(self.land(state, position, orientation.spin(clockwise)));
+                },
+                _ => return Some(self.land(state, position, orientation)),
+            }
+        }
+        return None;
+    }
+
+    // The state once the die comes to rest at `position`
+    #[allow(clippy::needless_return)]
+    fn land(&self, state: &PuzzleState, position: (usize, usize), orientation: DieOrientation) -> PuzzleState {
+        let tile_type = self.tiles[position.1][position.0];
+        let orientation = match tile_type {
+            TileType::Paint => orientation.paint(self.paints[&position]),
+            _ => orientation,
+        };
+        let mut landed = PuzzleState {
+            position,
+            orientation,
+            // Plates other than latching ones let go as soon as the die or crate leaves
+            activated_plates: state.activated_plates & self.latching_plates,
+            ..*state
+        };
+        if tile_type == TileType::Switch {
+            landed.switches_on ^= 1 << self.switch_indices[&position];
+        }
+
+        // Pushed crates reach their plates before the die lands
+        self.press_resting_plates(&mut landed);
+        if tile_type.pressure_plate_number() == Some(orientation.face) {
+            self.press_plate(&mut landed, position);
+        }
+        self.press_resting_plates(&mut landed);
+        return landed;
+    }
+
+    // Activates the plate at `position` as if the right number had landed on it, unless it's out of turn
+    fn press_plate(&self, state: &mut PuzzleState, position: (usize, usize)) {
+        let plate_bit = 1 << self.plate_indices[&position];
+        let already_completed = state.completed_plates & plate_bit != 0;
+        let order_step = self.plate_order_steps.get(&position).copied();
+        if already_completed || order_step.map_or(true, |step| step == state.plate_order_next) {
+            if !already_completed && order_step.is_some() {
+                state.plate_order_next += 1;
+            }
+            state.activated_plates |= plate_bit;
+            state.completed_plates |= plate_bit;
+        }
+        else if state.plate_order_next > 0 {
+            // Out of turn, so the order starts again
+            state.plate_order_next = 0;
+            state.activated_plates &= !self.ordered_plates;
+            state.completed_plates &= !self.ordered_plates;
+        }
+    }
+
+    // Crates hold down whatever plate they sit on, and so do the dice not being rolled if they have the right number up.
+    // Pressing one can put the next one in the plate order in turn, so go round until nothing changes, like the game
+    // does checking the plates every tick.
+    fn press_resting_plates(&self, state: &mut PuzzleState) {
+        // Pressing plates doesn't move anything, so these stay the same each time round
+        let crates = state.crates;
+        let other_dice = state.other_dice;
+        let pressing = crates
+            .iter()
+            .filter(|position| self.plate_indices.contains_key(position))
+            .chain(other_dice.iter().flatten().filter(|(position, orientation)|
+                self.tiles[position.1][position.0].pressure_plate_number() == Some(orientation.face)).map(|(position, _)| position));
+
+        for _ in 0..=MAX_CRATES + MAX_DICE {
+            let before = *state;
+            for position in pressing.clone() {
+                self.press_plate(state, *position);
+            }
+            if *state == before { return; }
+        }
+    }
+
+    // The shortest sequence of rolls from `start` that solves the level, or None if there isn't one
+    pub fn solve_from(&self, start: PuzzleState) -> Option<Vec<Roll>> {
+        match Search::new(start).advance(self, usize::MAX) {
+            SearchProgress::Solved(rolls) => Some(rolls),
+            SearchProgress::Searching | SearchProgress::Unsolvable => None,
+        }
+    }
+
+    // The same state, but with `other_dice[index]` as the die being rolled
+    #[allow(clippy::needless_return)]
+    pub fn switch_to(state: &PuzzleState, index: usize) -> PuzzleState {
+        let mut switched = *state;
+        let Some((position, orientation)) = state.other_dice[index] else { return switched; };
+        switched.position = position;
+        switched.orientation = orientation;
+        switched.other_dice[index] = Some((state.position, state.orientation));
+        let dice: Vec<_> = switched.other_dice.iter().flatten().copied().collect();
+        switched.other_dice = sorted_dice(&dice);
+        return switched;
+    }
+
+    // The state before each of `rolls`, starting from `start`. None if one of them can't be made.
+    #[allow(clippy::needless_return)]
+    pub fn states_along(&self, start: PuzzleState, rolls: &[Roll]) -> Option<Vec<PuzzleState>> {
+        let mut states = Vec::new();
+        let mut state = start;
+        for roll in rolls {
+            states.push(state);
+            let controlled = if state.position == roll.die {
+                state
+            } else {
+                let index = state.other_dice.iter().position(|die| die.is_some_and(|(position, _)| position == roll.die))?;
+                Self::switch_to(&state, index)
+            };
+            state = self.step(&controlled, roll.direction)?;
+        }
+        return Some(states);
+    }
+
+    // The same state with the dice in a fixed order, so it doesn't matter which die is the one being rolled
+    #[allow(clippy::needless_return)]
+    pub fn canonical(state: &PuzzleState) -> PuzzleState {
+        let first = state.other_dice
+            .iter()
+            .enumerate()
+            .filter_map(|(index, die)| die.map(|(position, _)| (position, index)))
+            .min();
+        match first {
+            Some((position, index)) if position < state.position => return Self::switch_to(state, index),
+            _ => return *state,
+        }
+    }
+
+    #[allow(clippy::needless_return)]
+    fn neighbour(&self, position: (usize, usize), direction: Direction) -> Option<(usize, usize)> {
+        let (column, row) = position;
+        let neighbour = match direction {
+            Direction::Up => (column, row.checked_sub(1)?),
+            Direction::Down => (column, row + 1),
+            Direction::Left => (column.checked_sub(1)?, row),
+            Direction::Right => (column + 1, row),
+        };
+
+        if neighbour.1 >= self.tiles.len() || neighbour.0 >= self.tiles[neighbour.1].len() { return None; }
+        return Some(neighbour);
+    }
+
+    // Like `neighbour`, but None if the die can't go there, arriving with `face` up
+    #[allow(clippy::needless_return)]
+    fn open_neighbour(&self, state: &PuzzleState, position: (usize, usize), direction: Direction, face: usize) -> Option<(usize, usize)> {
+        let neighbour = self.neighbour(position, direction)?;
+        if state.crates.contains(&neighbour) || self.has_other_die(state, neighbour) { return None; }
+        match self.tiles[neighbour.1][neighbour.0] {
+            TileType::Wall => return None,
+            TileType::Door { open } if open != self.is_door_swapped(state, neighbour) => return Some(neighbour),
+            TileType::Door { .. } => return None,
+            TileType::FaceFilter if !self.face_filters[&neighbour].allows(face) => return None,
+            _ => return Some(neighbour),
+        }
+    }
+
+    // Crates can be pushed onto anything but walls, closed doors, pits, dice and other crates
+    #[allow(clippy::needless_return)]
+    fn can_take_crate(&self, state: &PuzzleState, position: (usize, usize)) -> bool {
+        if state.crates.contains(&position) || self.has_other_die(state, position) || self.is_crumbled(state, position) { return false; }
+        match self.tiles[position.1][position.0] {
+            TileType::Wall | TileType::Pit => return false,
+            TileType::Door { open } => return open != self.is_door_swapped(state, position),
+            _ => return true,
+        }
+    }
+
+    fn has_other_die(&self, state: &PuzzleState, position: (usize, usize)) -> bool {
+        state.other_dice.iter().flatten().any(|(die_position, _)| *die_position == position)
+    }
+
+    fn is_crumbled(&self, state: &PuzzleState, position: (usize, usize)) -> bool {
+        self.crumbling_index(position).is_some_and(|index| state.crumbled & 1 << index != 0)
+    }
+
+    // Whether the door has been swapped from how it started, by its linked plates and switches
+    #[allow(clippy::needless_return)]
+    fn is_door_swapped(&self, state: &PuzzleState, position: (usize, usize)) -> bool {
+        let Some(link) = self.links.get(&position) else { return false; };
+        let (plate_bits, switch_bits) = self.linked_bits.get(link).copied().unwrap_or_default();
+        let swaps = (state.activated_plates & plate_bits).count_ones() + (state.switches_on & switch_bits).count_ones();
+        return swaps % 2 == 1;
+    }
+}
+
+// Breadth first search, so the first solution found is one of the shortest. It can be done a few states at a time, so
+// solving a big level doesn't have to hold everything else up. Switching between dice is free, so any of them can make
+// the next roll.
+pub struct Search {
+    pieces: Pieces,
+    // Every state reached, in the order they were reached, with the state before it and the roll from there. This
+    // doubles as the queue of states to visit.
+    reached: Vec<(PackedState, Option<(usize, Roll)>)>,
+    seen: HashSet<PackedState>,
+    visiting: usize,
+}
+
+pub enum SearchProgress {
+    Searching,
+    Solved(Vec<Roll>),
+    Unsolvable,
+}
+
+impl Search {
+    pub fn new(start: PuzzleState) -> Search {
+        let mut pieces = Pieces::default();
+        let start = pieces.pack(&start, 0);
+        Search {
+            pieces,
+            reached: vec![(start, None)],
+            seen: HashSet::from([start]),
+            visiting: 0,
+        }
+    }
+
+    // Visits up to `max_states` more states of `puzzle`, stopping early if it runs out or finds a solution
+    #[allow(clippy::needless_return)]
+    pub fn advance(&mut self, puzzle: &Puzzle, max_states: usize) -> SearchProgress {
+        for _ in 0..max_states {
+            let Some(&(packed, _)) = self.reached.get(self.visiting) else { return SearchProgress::Unsolvable; };
+            let state = self.pieces.unpack(&packed);
+            if puzzle.is_solved(&state) {
+                return SearchProgress::Solved(self.moves_to(self.visiting));
+            }
+
+            let other_dice = state.other_dice.iter().enumerate().filter(|(_, die)| die.is_some());
+            let controlled = std::iter::once(state).chain(other_dice.map(|(index, _)| Puzzle::switch_to(&state, index)));
+            for controlled in controlled {
+                for direction in DIRECTIONS {
+                    let Some(next) = puzzle.step(&controlled, direction) else { continue; };
+                    let next = self.pieces.pack(&next, packed.pieces);
+                    if !self.seen.insert(next) { continue; }
+
+                    self.reached.push((next, Some((self.visiting, Roll { die: controlled.position, direction }))));
+                }
+            }
+            self.visiting += 1;
+        }
+        return SearchProgress::Searching;
+    }
+
+    // The rolls that lead to `reached[end]`, following each state back to the one before it
+    #[allow(clippy::needless_return)]
+    fn moves_to(&self, end: usize) -> Vec<Roll> {
+        let mut moves = Vec::new();
+        let mut index = end;
+        while let Some((previous, roll)) = self.reached[index].1 {
+            moves.push(roll);
+            index = previous;
+        }
+        moves.reverse();
+        return moves;
+    }
+}
+
+// The shortest sequence of rolls that activates every plate, or None if the level can't be beaten.
+// With more than one die, `Puzzle::solve_from` also says which die makes each roll.
+pub fn solve(level: &Level) -> Option<Vec<Direction>> {
+    let puzzle = Puzzle::new(level);
+    let rolls = puzzle.solve_from(puzzle.start_state())?;
+    return Some(rolls.iter().map(|roll| roll.direction).collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(tiles: &[&str], die_start: (usize, usize)) -> Level {
+        level_with(tiles, die_start, "")
+    }
+
+    // `extra` is any other fields for the level file, like `links`
+    fn level_with(tiles: &[&str], die_start: (usize, usize), extra: &str) -> Level {
+        let rows: Vec<String> = tiles.iter().map(|row| format!("\"{}\"", row)).collect();
+        let ron = format!(
+            "(tiles: [{}], die_start: {:?}, die_orientation: (face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6), {})",
+            rows.join(","),
+            die_start,
+            extra);
+        Level::from_ron(ron.as_bytes()).unwrap()
+    }
+
+    #[allow(clippy::needless_return)]
+    fn replay(level: &Level, moves: &[Direction]) -> PuzzleState {
+        let puzzle = Puzzle::new(level);
+        let mut state = puzzle.start_state();
+        for direction in moves {
+            state = puzzle.step(&state, *direction).expect("solution rolls into a wall");
+        }
+        assert!(puzzle.is_solved(&state));
+        return state;
+    }
+
+    #[test]
+    fn single_roll_onto_a_plate() {
+        let level = level(&[
+            "#####",
+            "#.5.#",
+            "#...#",
+            "#####"], (2, 2));
+        assert_eq!(solve(&level), Some(vec![Direction::Up]));
+    }
+
+    #[test]
+    fn no_plates_is_already_solved() {
+        let level = level(&[
+            "###",
+            "#.#",
+            "###"], (1, 1));
+        assert_eq!(solve(&level), Some(vec![]));
+    }
+
+    #[test]
+    fn finds_the_shortest_route() {
+        // The 6 is underneath, so it takes two rolls in a straight line to bring it up
+        let level = level(&[
+            "######",
+            "#....#",
+            "#...6#",
+            "#....#",
+            "######"], (2, 2));
+        let moves = solve(&level).unwrap();
+        assert_eq!(moves, vec![Direction::Right, Direction::Right]);
+        replay(&level, &moves);
+    }
+
+    #[test]
+    fn walls_block_the_die() {
+        let level = level(&[
+            "#######",
+            "#..#..#",
+            "#..#.3#",
+            "#.....#",
+            "#######"], (1, 1));
+        let moves = solve(&level).unwrap();
+        replay(&level, &moves);
+        assert!(moves.len() > 5);
+    }
+
+    #[test]
+    fn unreachable_plates_are_unsolvable() {
+        let level = level(&[
+            "######",
+            "#..#4#",
+            "#..###",
+            "######"], (1, 1));
+        assert_eq!(solve(&level), None);
+    }
+
+    #[test]
+    fn corridor_only_shows_four_faces() {
+        // Rolling left and right only ever shows 1, 3, 6 and 4 face up
+        let level = level(&[
+            "#######",
+            "#..2..#",
+            "#######"], (1, 1));
+        assert_eq!(solve(&level), None);
+    }
+
+    #[test]
+    fn ice_slides_without_rolling() {
+        let level = level(&[
+            "#######",
+            "#.~~~.#",
+            "#######"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (5, 1));
+        assert_eq!(state.orientation, DieOrientation::default().roll(Direction::Right));
+    }
+
+    #[test]
+    fn ice_stops_against_walls() {
+        let level = level(&[
+            "######",
+            "#.~~~#",
+            "######"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (4, 1));
+    }
+
+    #[test]
+    fn sliding_stops_on_the_first_tile_past_the_ice() {
+        // Rolling right brings the 4 up, and the slide carries it onto the plate
+        let level = level(&[
+            "#######",
+            "#.~~4.#",
+            "#######"], (1, 1));
+        assert_eq!(solve(&level), Some(vec![Direction::Right]));
+    }
+
+    #[test]
+    fn teleporters_move_the_die_to_their_partner() {
+        let level = level(&[
+            "#######",
+            "#.a#a.#",
+            "#######"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (4, 1));
+        assert_eq!(state.orientation, DieOrientation::default().roll(Direction::Right));
+    }
+
+    #[test]
+    fn teleporters_reach_other_rooms() {
+        let level = level(&[
+            "########",
+            "#.a#b..#",
+            "#..#...#",
+            "#b.#.a5#",
+            "########"], (1, 1));
+        let moves = solve(&level).unwrap();
+        replay(&level, &moves);
+    }
+
+    #[test]
+    fn conveyors_roll_the_die_along() {
+        let level = level(&[
+            "########",
+            "#.>>...#",
+            "########"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (4, 1));
+        assert_eq!(state.orientation, DieOrientation::default()
+            .roll(Direction::Right)
+            .roll(Direction::Right)
+            .roll(Direction::Right));
+    }
+
+    #[test]
+    fn conveyors_turn_the_die() {
+        let level = level(&[
+            "#####",
+            "#.V.#",
+            "#.>.#",
+            "#####"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (3, 2));
+    }
+
+    #[test]
+    fn conveyors_stop_at_walls() {
+        let level = level(&[
+            "#####",
+            "#.>>#",
+            "#####"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (3, 1));
+    }
+
+    #[test]
+    fn rotators_spin_the_die_in_place() {
+        let level = level(&[
+            "#####",
+            "#.).#",
+            "#.(.#",
+            "#####"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let rolled = DieOrientation::default().roll(Direction::Right);
+
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (2, 1));
+        assert_eq!(state.orientation, rolled.spin(true));
+
+        let state = puzzle.step(&PuzzleState { position: (1, 2), ..puzzle.start_state() }, Direction::Right).unwrap();
+        assert_eq!(state.orientation, rolled.spin(false));
+    }
+
+    #[test]
+    fn switches_open_doors() {
+        let level = level_with(&[
+            "######",
+            "#*.|6#",
+            "######"], (2, 1), "links: {(1, 1): 0, (3, 1): 0}");
+        let puzzle = Puzzle::new(&level);
+        assert_eq!(puzzle.step(&puzzle.start_state(), Direction::Right), None);
+
+        let moves = solve(&level).unwrap();
+        replay(&level, &moves);
+        assert_eq!(moves[0], Direction::Left);
+    }
+
+    #[test]
+    fn pressing_a_switch_again_closes_the_door() {
+        let level = level_with(&[
+            "#######",
+            "#.*.|.#",
+            "#######"], (3, 1), "links: {(2, 1): 0, (4, 1): 0}");
+        let puzzle = Puzzle::new(&level);
+        let start = puzzle.start_state();
+        let on = puzzle.step(&start, Direction::Left).unwrap();
+        let back = puzzle.step(&on, Direction::Right).unwrap();
+        assert!(puzzle.step(&back, Direction::Right).is_some());
+
+        let off = puzzle.step(&back, Direction::Left).unwrap();
+        assert_eq!(off.switches_on, 0);
+        let back = puzzle.step(&off, Direction::Right).unwrap();
+        assert_eq!(puzzle.step(&back, Direction::Right), None);
+    }
+
+    #[test]
+    fn plates_open_doors() {
+        // The 3 on the right is behind the door, so the one on the left has to be activated first
+        let level = level_with(&[
+            "#######",
+            "#3.|.3#",
+            "#######"], (2, 1), "links: {(1, 1): 0, (3, 1): 0}");
+        let puzzle = Puzzle::new(&level);
+        assert_eq!(puzzle.step(&puzzle.start_state(), Direction::Right), None);
+
+        let moves = solve(&level).unwrap();
+        replay(&level, &moves);
+        assert_eq!(moves[0], Direction::Left);
+    }
+
+    #[test]
+    fn momentary_plates_let_go_when_the_die_leaves() {
+        let level = level_with(&[
+            "######",
+            "#..4.#",
+            "######"], (2, 1), "plate_modes: {(3, 1): Momentary}");
+        let puzzle = Puzzle::new(&level);
+        let on_plate = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(on_plate.activated_plates, 1);
+        let off_plate = puzzle.step(&on_plate, Direction::Right).unwrap();
+        assert_eq!(off_plate.activated_plates, 0);
+        assert!(puzzle.is_solved(&off_plate));
+    }
+
+    #[test]
+    fn held_plates_must_be_activated_at_the_end() {
+        // Both plates are reachable, but the die can only sit on one of them at a time
+        let level = level_with(&[
+            "#######",
+            "#3.4..#",
+            "#######"], (2, 1), "plate_modes: {(1, 1): Held, (3, 1): Held}");
+        assert_eq!(solve(&level), None);
+
+        let level = level_with(&[
+            "#######",
+            "#3.4..#",
+            "#######"], (2, 1), "plate_modes: {(1, 1): Momentary, (3, 1): Held}");
+        let moves = solve(&level).unwrap();
+        assert_eq!(moves, vec![Direction::Left, Direction::Right, Direction::Right]);
+    }
+
+    #[test]
+    fn momentary_plates_only_hold_doors_open_while_pressed() {
+        // The door in the way of the 1 shuts again as soon as the die rolls off the 4
+        let level = level_with(&[
+            "########",
+            "#..4.|1#",
+            "########"], (2, 1), "plate_modes: {(3, 1): Momentary}, links: {(3, 1): 0, (5, 1): 0}");
+        assert_eq!(solve(&level), None);
+
+        let level = level_with(&[
+            "########",
+            "#..4.|1#",
+            "########"], (2, 1), "links: {(3, 1): 0, (5, 1): 0}");
+        assert!(solve(&level).is_some());
+    }
+
+    #[test]
+    fn ordered_plates_must_be_activated_in_order() {
+        // Without an order the 3 on the left is closest, but it has to come after the 4
+        let tiles = [
+            "#######",
+            "#3.4..#",
+            "#######"];
+        assert_eq!(solve(&level(&tiles, (2, 1))).unwrap().len(), 3);
+
+        let level = level_with(&tiles, (2, 1), "plate_order: [(3, 1), (1, 1)]");
+        let moves = solve(&level).unwrap();
+        replay(&level, &moves);
+        assert_eq!(moves, vec![Direction::Right, Direction::Left, Direction::Left]);
+    }
+
+    #[test]
+    fn out_of_order_plates_start_the_order_again() {
+        let level = level_with(&[
+            "########",
+            "#3.4..1#",
+            "########"], (2, 1), "plate_order: [(3, 1), (6, 1), (1, 1)]");
+        let puzzle = Puzzle::new(&level);
+        let first = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(first.plate_order_next, 1);
+
+        let reset = puzzle.step(&puzzle.step(&first, Direction::Left).unwrap(), Direction::Left).unwrap();
+        assert_eq!(reset.plate_order_next, 0);
+        assert_eq!(reset.completed_plates, 0);
+        assert_eq!(reset.activated_plates, 0);
+    }
+
+    #[test]
+    fn pits_are_avoided() {
+        let level = level(&[
+            "######",
+            "#....#",
+            "#....#",
+            "#.O4.#",
+            "######"], (1, 3));
+        let puzzle = Puzzle::new(&level);
+        assert_eq!(puzzle.step(&puzzle.start_state(), Direction::Right), None);
+
+        let moves = solve(&level).unwrap();
+        replay(&level, &moves);
+        assert!(moves.len() > 2);
+    }
+
+    #[test]
+    fn crumbling_floor_can_only_be_crossed_once() {
+        let level = level(&[
+            "#####",
+            "#.%.#",
+            "#####"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let on_floor = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(on_floor.crumbled, 0);
+        let past_floor = puzzle.step(&on_floor, Direction::Right).unwrap();
+        assert_eq!(past_floor.crumbled, 1);
+        assert_eq!(puzzle.step(&past_floor, Direction::Left), None);
+    }
+
+    #[test]
+    fn crumbling_floor_forces_the_order() {
+        // Going for the 6 first would leave no way back over the crumbling floor to the 3
+        let level = level(&[
+            "######",
+            "#3.%6#",
+            "######"], (2, 1));
+        let moves = solve(&level).unwrap();
+        replay(&level, &moves);
+        assert_eq!(moves, vec![Direction::Left, Direction::Right, Direction::Right, Direction::Right]);
+    }
+
+    #[test]
+    fn face_filters_only_let_matching_numbers_through() {
+        // Rolling right brings the 4 face up
+        let tiles = [
+            "#####",
+            "#.?.#",
+            "#####"];
+        for (filter, allowed) in [("Number(4)", true), ("Number(3)", false), ("Even", true), ("Odd", false)] {
+            let level = level_with(&tiles, (1, 1), &format!("face_filters: {{(2, 1): {}}}", filter));
+            let puzzle = Puzzle::new(&level);
+            let step = puzzle.step(&puzzle.start_state(), Direction::Right);
+            assert_eq!(step.is_some(), allowed, "{}", filter);
+        }
+    }
+
+    #[test]
+    fn sliding_onto_a_face_filter_keeps_the_same_number_up() {
+        let tiles = [
+            "######",
+            "#.~?.#",
+            "######"];
+        let level = level_with(&tiles, (1, 1), "face_filters: {(3, 1): Number(4)}");
+        let puzzle = Puzzle::new(&level);
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (3, 1));
+
+        let level = level_with(&tiles, (1, 1), "face_filters: {(3, 1): Number(3)}");
+        let puzzle = Puzzle::new(&level);
+        let state = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(state.position, (2, 1));
+    }
+
+    #[test]
+    fn paint_relabels_the_side_against_the_floor() {
+        // Rolling along a corridor never brings the 2 face up, unless it gets painted on
+        let level = level(&[
+            "######",
+            "#...2#",
+            "######"], (1, 1));
+        assert_eq!(solve(&level), None);
+
+        let level = level_with(&[
+            "######",
+            "#.@.2#",
+            "######"], (1, 1), "paints: {(2, 1): 2}");
+        let puzzle = Puzzle::new(&level);
+        let painted = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!(painted.orientation.hidden, 2);
+        assert_eq!(solve(&level).unwrap(), vec![Direction::Right, Direction::Right, Direction::Right]);
+    }
+
+    #[test]
+    fn crates_are_pushed_until_something_is_in_the_way() {
+        let level = level_with(&[
+            "######",
+            "#....#",
+            "######"], (1, 1), "crates: [(2, 1)]");
+        let puzzle = Puzzle::new(&level);
+        let pushed = puzzle.step(&puzzle.start_state(), Direction::Right).unwrap();
+        assert_eq!((pushed.position, pushed.crates[0]), ((2, 1), (3, 1)));
+        let pushed = puzzle.step(&pushed, Direction::Right).unwrap();
+        assert_eq!((pushed.position, pushed.crates[0]), ((3, 1), (4, 1)));
+        assert_eq!(puzzle.step(&pushed, Direction::Right), None);
+    }
+
+    #[test]
+    fn crates_cant_be_pushed_into_pits_or_other_crates() {
+        let level = level_with(&[
+            "######",
+            "#..O.#",
+            "######"], (1, 1), "crates: [(2, 1)]");
+        let puzzle = Puzzle::new(&level);
+        assert_eq!(puzzle.step(&puzzle.start_state(), Direction::Right), None);
+
+        let level = level_with(&[
+            "######",
+            "#....#",
+            "######"], (1, 1), "crates: [(2, 1), (3, 1)]");
+        let puzzle = Puzzle::new(&level);
+        assert_eq!(puzzle.step(&puzzle.start_state(), Direction::Right), None);
+    }
+
+    #[test]
+    fn crates_hold_plates_down_whatever_their_number() {
+        // The die can only reach the 3 with the 6 face up
+        let tiles = [
+            "######",
+            "#..3.#",
+            "######"];
+        assert_eq!(solve(&level(&tiles, (1, 1))), None);
+        assert_eq!(solve(&level_with(&tiles, (1, 1), "crates: [(2, 1)]")).unwrap(), vec![Direction::Right]);
+    }
+
+    #[test]
+    fn dice_and_crates_start_out_pressing_plates() {
+        let level = level_with(&[
+            "#####",
+            "#1.3#",
+            "#####"], (1, 1), "crates: [(3, 1)]");
+        let puzzle = Puzzle::new(&level);
+        let start = puzzle.start_state();
+        assert!(puzzle.is_solved(&start));
+        assert_eq!(start.activated_plates, 0b11);
+    }
+
+    #[test]
+    fn states_along_a_solution_lead_to_it_being_solved() {
+        let level = level(&[
+            "#######",
+            "#.....#",
+            "#..4..#",
+            "#######"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let start = puzzle.start_state();
+        let rolls = puzzle.solve_from(start).unwrap();
+        let states = puzzle.states_along(start, &rolls).unwrap();
+        assert_eq!(states.len(), rolls.len());
+        assert_eq!(states[0], start);
+
+        // Solving again from partway along carries on the same way
+        let rest = puzzle.solve_from(states[1]).unwrap();
+        assert_eq!(rest.len(), rolls.len() - 1);
+    }
+
+    #[test]
+    fn searching_a_few_states_at_a_time_finds_the_same_solution() {
+        let level = level(&[
+            "#######",
+            "#.....#",
+            "#..4..#",
+            "#######"], (1, 1));
+        let puzzle = Puzzle::new(&level);
+        let start = puzzle.start_state();
+
+        let mut search = Search::new(start);
+        let mut advances = 0;
+        let rolls = loop {
+            advances += 1;
+            match search.advance(&puzzle, 3) {
+                SearchProgress::Searching => continue,
+                SearchProgress::Solved(rolls) => break rolls,
+                SearchProgress::Unsolvable => panic!("level should be solvable"),
+            }
+        };
+        assert!(advances > 1);
+        assert_eq!(rolls, puzzle.solve_from(start).unwrap());
+
+        let walled_in = level_with(&["###", "#.#", "###", "#1#"], (1, 1), "");
+        let puzzle = Puzzle::new(&walled_in);
+        assert!(matches!(Search::new(puzzle.start_state()).advance(&puzzle, 100), SearchProgress::Unsolvable));
+    }
+
+    const EXTRA_DIE_ORIENTATION: &str = "(face: 1, top: 2, right: 3, bottom: 5, left: 4, hidden: 6)";
+
+    #[test]
+    fn dice_block_each_other() {
+        let level = level_with(&[
+            "######",
+            "#....#",
+            "######"], (1, 1), &format!("extra_dice: [(position: (2, 1), orientation: {})]", EXTRA_DIE_ORIENTATION));
+        let puzzle = Puzzle::new(&level);
+        let start = puzzle.start_state();
+        assert_eq!(puzzle.step(&start, Direction::Right), None);
+        assert_eq!(puzzle.step(&Puzzle::switch_to(&start, 0), Direction::Left), None);
+    }
+
+    #[test]
+    fn solutions_can_roll_more_than_one_die() {
+        // Each die is walled into its own column with a plate only it can reach
+        let level = level_with(&[
+            "#####",
+            "#.#.#",
+            "#2#2#",
+            "#####"], (1, 1), &format!("extra_dice: [(position: (3, 1), orientation: {})]", EXTRA_DIE_ORIENTATION));
+        let puzzle = Puzzle::new(&level);
+        let rolls = puzzle.solve_from(puzzle.start_state()).unwrap();
+        assert_eq!(rolls.len(), 2);
+        assert!(rolls.contains(&Roll { die: (1, 1), direction: Direction::Down }));
+        assert!(rolls.contains(&Roll { die: (3, 1), direction: Direction::Down }));
+    }
+
+    #[test]
+    fn campaign_levels_are_solvable() {
+        for file in [
+            include_str!("../assets/levels/level_01.level.ron"),
+            include_str!("../assets/levels/level_02.level.ron"),
+            include_str!("../assets/levels/level_03.level.ron"),
+        ] {
+            let level = Level::from_ron(file.as_bytes()).unwrap();
+            let moves = solve(&level).expect("campaign level can't be solved");
+            replay(&level, &moves);
+        }
+    }
+
+    #[test]
+    fn versus_levels_can_be_finished_by_either_player() {
+        for file in [
+            include_str!("../assets/levels/versus_01.level.ron"),
+            include_str!("../assets/levels/versus_02.level.ron"),
+        ] {
+            let race = Level::from_ron(file.as_bytes()).unwrap();
+            assert_eq!(race.dice().len(), 2);
+
+            // Solving for both dice at once is too slow, but if either die could do it alone neither gets shut out
+            for die in race.dice() {
+                let mut level = Level::from_ron(file.as_bytes()).unwrap();
+                level.die_start = die.position;
+                level.die_orientation = die.orientation;
+                level.extra_dice.clear();
+                let moves = solve(&level).expect("versus level can't be solved");
+                replay(&level, &moves);
+            }
+        }
+    }
+}
@@ -1,5 +1,11 @@
 use bevy::prelude::*;
-use crate::{GameState, direction::keypress_to_direction};
+use crate::{
+    GameState,
+    daily::Date,
+    key_bindings::{Action, KeyBindings},
+    level::Level,
+    world_plugin::{Campaign, CurrentLevel, StoryLevels, VersusLevels},
+};
 
 pub struct TitleScreenPlugin;
 
@@ -37,16 +43,24 @@ fn setup(
                 right: Val::Px(15.0),
                 ..default()
             },
-            // Use the `Text::with_section` constructor
-            text: Text::from_section(
-                // Accepts a `String` or any type that converts into a `String`, such as `&str`
-                "Roll the Die!",
-                TextStyle {
-                    font: asset_server.load("fonts/FreeSans.ttf"),
-                    font_size: 100.0,
-                    color: Color::WHITE,
-                },
-            ).with_alignment(TextAlignment::Center),
+            text: Text::from_sections([
+                TextSection::new(
+                    "Roll the Die!\n",
+                    TextStyle {
+                        font: asset_server.load("fonts/FreeSans.ttf"),
+                        font_size: 100.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FreeSans.ttf"),
+                        font_size: 30.0,
+                        color: Color::WHITE,
+                    },
+                ),
+            ]).with_alignment(TextAlignment::Center),
             visibility: Visibility::Hidden,
             ..default()
         })
@@ -54,24 +68,59 @@ fn setup(
 }
 
 fn show_main_menu(
+    key_bindings: Res<KeyBindings>,
     mut query: Query<
-        &mut Visibility,
+        (&mut Visibility, &mut Text),
         With<MenuUi>>,
 ) {
-    for mut visibility in query.iter_mut() {
+    for (mut visibility, mut text) in query.iter_mut() {
         *visibility = Visibility::Visible;
+        text.sections[1].value = format!(
+            "{}: campaign    {:?}: endless    {:?}: daily puzzle    {:?}: two players    {:?}: settings",
+            key_bindings.move_keys_name(),
+            key_bindings.key(Action::Endless),
+            key_bindings.key(Action::Daily),
+            key_bindings.key(Action::TwoPlayer),
+            key_bindings.key(Action::Pause));
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn update_main_menu(
     keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    time: Res<Time>,
+    story_levels: Res<StoryLevels>,
+    versus_levels: Res<VersusLevels>,
+    mut levels: ResMut<Assets<Level>>,
+    mut campaign: ResMut<Campaign>,
+    mut current_level: ResMut<CurrentLevel>,
     mut state: ResMut<NextState<GameState>>,
 ) {
-    let direction = keypress_to_direction(keyboard_input);
-
-    if direction.is_some() {
-        state.set(GameState::Playing);
+    // Moving comes first, so a move key that's also a shortcut (after loading old bindings, say) still starts the campaign
+    if key_bindings.direction(&keyboard_input).is_some() {
+        *campaign = Campaign::story(&story_levels);
+    }
+    else if key_bindings.just_pressed(&keyboard_input, Action::Endless) {
+        // How long the menu was up for is as good a seed as any
+        *campaign = Campaign::endless(time.raw_elapsed().as_nanos() as u64, &mut levels);
     }
+    else if key_bindings.just_pressed(&keyboard_input, Action::Daily) {
+        *campaign = Campaign::daily(Date::today(), &mut levels);
+    }
+    else if key_bindings.just_pressed(&keyboard_input, Action::TwoPlayer) {
+        if key_bindings.player_two_keys().is_none() {
+            log::warn!("Player two needs WASD or the arrow keys, but both have other actions bound to them");
+            return;
+        }
+        *campaign = Campaign::versus(&versus_levels);
+    }
+    else {
+        return;
+    }
+
+    *current_level = CurrentLevel(0);
+    state.set(GameState::Playing);
 }
 
 fn hide_main_menu(
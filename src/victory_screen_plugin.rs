@@ -1,5 +1,5 @@
 use bevy::prelude::*;
-use crate::GameState;
+use crate::{GameState, PressurePlate, level::Level, world_plugin::{Campaign, CampaignMode, CurrentLevel, LevelStats}};
 
 pub struct VictoryScreenPlugin;
 
@@ -46,16 +46,24 @@ fn setup(
                 right: Val::Px(15.0),
                 ..default()
             },
-            // Use the `Text::with_section` constructor
-            text: Text::from_section(
-                // Accepts a `String` or any type that converts into a `String`, such as `&str`
-                "Congratulations!",
-                TextStyle {
-                    font: asset_server.load("fonts/FreeSans.ttf"),
-                    font_size: 100.0,
-                    color: Color::WHITE,
-                },
-            ).with_alignment(TextAlignment::Center),
+            text: Text::from_sections([
+                TextSection::new(
+                    "Congratulations!\n",
+                    TextStyle {
+                        font: asset_server.load("fonts/FreeSans.ttf"),
+                        font_size: 100.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                TextSection::new(
+                    "",
+                    TextStyle {
+                        font: asset_server.load("fonts/FreeSans.ttf"),
+                        font_size: 40.0,
+                        color: Color::WHITE,
+                    },
+                ),
+            ]).with_alignment(TextAlignment::Center),
             visibility: Visibility::Hidden,
             ..default()
         })
@@ -63,16 +71,55 @@ fn setup(
         .insert(VictoryTimer(Timer::from_seconds(5.0, TimerMode::Once)));
 }
 
+const PLAYER_NAMES: [&str; 2] = ["Player one", "Player two"];
+
 fn show(
+    campaign: Res<Campaign>,
+    current_level: Res<CurrentLevel>,
+    level_stats: Res<LevelStats>,
+    pressure_plates_query: Query<&PressurePlate>,
     mut query: Query<
-        &mut Visibility,
+        (&mut Visibility, &mut Text),
         With<VictoryUi>>,
 ) {
-    for mut visibility in query.iter_mut() {
+    for (mut visibility, mut text) in query.iter_mut() {
         *visibility = Visibility::Visible;
+        if campaign.mode == CampaignMode::Versus {
+            let (winner, details) = race_result(&pressure_plates_query);
+            text.sections[0].value = winner;
+            text.sections[1].value = details;
+            continue;
+        }
+
+        text.sections[0].value = match &campaign.mode {
+            CampaignMode::Daily { date } => format!("Daily puzzle {} complete!\n", date),
+            _ if campaign.is_last(&current_level) => "Campaign complete!\n".to_string(),
+            _ => "Level complete!\n".to_string(),
+        };
+
+        let mut details = format!("{} moves", level_stats.moves);
+        if let CampaignMode::Daily { .. } = campaign.mode {
+            details += &format!(" in {:.1}s", level_stats.time.elapsed_secs());
+        }
+        if let (Some(par), Some(stars)) = (level_stats.par, level_stats.stars()) {
+            details += &format!(", par {}: {} of 3 stars", par, stars);
+        }
+        text.sections[1].value = details;
     }
 }
 
+// Whoever activated the most plates first wins the race
+#[allow(clippy::needless_return)]
+fn race_result(pressure_plates_query: &Query<&PressurePlate>) -> (String, String) {
+    let plates_won = PressurePlate::plates_won(pressure_plates_query.iter());
+    let winner = match plates_won[0].cmp(&plates_won[1]) {
+        std::cmp::Ordering::Greater => format!("{} wins!\n", PLAYER_NAMES[0]),
+        std::cmp::Ordering::Less => format!("{} wins!\n", PLAYER_NAMES[1]),
+        std::cmp::Ordering::Equal => "It's a draw!\n".to_string(),
+    };
+    return (winner, format!("Plates: {} to {}", plates_won[0], plates_won[1]));
+}
+
 fn start_timer(
     mut query: Query<&mut VictoryTimer>,
 ) {
@@ -82,6 +129,9 @@ fn start_timer(
 
 fn update(
     time: Res<Time>,
+    mut campaign: ResMut<Campaign>,
+    mut current_level: ResMut<CurrentLevel>,
+    mut levels: ResMut<Assets<Level>>,
     mut query: Query<&mut VictoryTimer>,
     mut state: ResMut<NextState<GameState>>,
 ) {
@@ -89,7 +139,13 @@ fn update(
     timer.0.tick(time.delta());
 
     if timer.0.percent_left() < 0.001 {
-        state.set(GameState::MainMenu);
+        if campaign.is_last(&current_level) {
+            *current_level = CurrentLevel(0);
+            state.set(GameState::MainMenu);
+        } else {
+            campaign.advance(&mut current_level, &mut levels);
+            state.set(GameState::Playing);
+        }
     }
 }
 
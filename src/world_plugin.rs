@@ -1,5 +1,14 @@
-use bevy::prelude::*;
-use crate::{GRID_SIZE, Collider, PressurePlate, Spritesheet};
+use bevy::{prelude::*, time::Stopwatch, utils::HashMap};
+use crate::{
+    GRID_SIZE, Collider, PressurePlate, Spritesheet, GameState,
+    die_plugin::{Die, PLAYER_COLOURS, spawn_die},
+    daily::Date,
+    direction::Direction,
+    key_bindings::{Action, KeyBindings},
+    level::{FaceFilter, Level, LevelLoader, PlateMode, TileType},
+    generator,
+    solver::{Puzzle, PuzzleState, Roll, Search, SearchProgress},
+};
 
 
 pub struct WorldPlugin;
@@ -7,24 +16,119 @@ pub struct WorldPlugin;
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app
-            .add_systems(PostStartup, setup)
-            .add_systems(Update, update_pressure_plate_appearence);
+            .add_asset::<Level>()
+            .init_asset_loader::<LevelLoader>()
+            .init_resource::<CurrentLevel>()
+            .init_resource::<SpawnedLevel>()
+            .init_resource::<LevelStats>()
+            .init_resource::<LevelSolution>()
+            .init_resource::<LevelSearch>()
+            .init_resource::<PlateOrder>()
+            .add_event::<LevelSpawned>()
+            .add_event::<RestartLevel>()
+            .add_systems(Startup, load_campaign)
+            // Before `Update`, so the victory check never sees the previous level's plates
+            .add_systems(PreUpdate, spawn_level)
+            .add_systems(Update, (update_pressure_plate_appearence, update_switch_appearence, update_next_plate_marker, advance_level_search))
+            .add_systems(FixedUpdate, (update_doors, crumble_floor))
+            .add_systems(
+                Update,
+                (
+                    restart_on_keypress,
+                    tick_level_time,
+                ).run_if(in_state(GameState::Playing))
+            );
     }
 }
 
-fn setup(
+fn load_campaign(
     mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let story_levels = StoryLevels(
+        CAMPAIGN_LEVELS.iter().map(|path| asset_server.load(*path)).collect()
+    );
+    commands.insert_resource(Campaign::story(&story_levels));
+    commands.insert_resource(story_levels);
+    commands.insert_resource(VersusLevels(
+        VERSUS_LEVELS.iter().map(|path| asset_server.load(*path)).collect()
+    ));
+}
+
+fn restart_on_keypress(
+    keyboard_input: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut restart_events: EventWriter<RestartLevel>,
+) {
+    if key_bindings.just_pressed(&keyboard_input, Action::Restart) {
+        restart_events.send(RestartLevel);
+    }
+}
+
+fn tick_level_time(
+    time: Res<Time>,
+    mut level_stats: ResMut<LevelStats>,
+) {
+    level_stats.time.tick(time.delta());
+}
+
+// Everything `spawn_level` puts out, so it can be cleared away again
+type LevelEntitiesQuery<'w, 's> = Query<'w, 's, Entity, Or<(With<Tile>, With<Die>)>>;
+
+// Spawning is the only way a level gets reset, so starting a new level, restarting and reloading all put it back the same way
+#[allow(clippy::too_many_arguments)]
+fn spawn_level(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut level_events: EventReader<AssetEvent<Level>>,
+    mut restart_events: EventReader<RestartLevel>,
+    campaign: Res<Campaign>,
+    current_level: Res<CurrentLevel>,
+    levels: Res<Assets<Level>>,
     spritesheet: Res<Spritesheet>,
+    old_level_query: LevelEntitiesQuery,
+    mut level_stats: ResMut<LevelStats>,
+    mut level_solution: ResMut<LevelSolution>,
+    mut level_search: ResMut<LevelSearch>,
+    mut plate_order: ResMut<PlateOrder>,
+    mut level_spawned_events: EventWriter<LevelSpawned>,
+    mut spawned_level: ResMut<SpawnedLevel>,
+    mut state: ResMut<NextState<GameState>>,
 ) {
-    let spritesheet = spritesheet.0.clone();
-    for y in 0 .. WORLD_SIZE {
-        for x in 0 .. WORLD_SIZE {
-            let tile_type = WORLD[y][x];
+    let level_handle = campaign.handle(&current_level);
 
-            let position = Vec3::new(
-                (x as f32 - WORLD_CENTRE as f32)  * GRID_SIZE, 
-                (y as f32 - WORLD_CENTRE as f32) * GRID_SIZE * -1.0, 
-                get_tile_height(&tile_type));
+    // Respawn when moving to another level, or when the current one finishes loading (or is hot reloaded)
+    let mut level_changed = current_level.is_changed() || campaign.is_changed();
+    for event in level_events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } => {
+                level_changed = level_changed || handle == level_handle;
+                level_search.solved.remove(handle);
+            },
+            AssetEvent::Removed { .. } => {},
+        }
+    }
+    let restarted = restart_events.iter().count() > 0;
+    if !level_changed && !restarted { return; }
+    let Some(level) = levels.get(level_handle) else { return; };
+
+    // Each player needs a die of their own, and any more would be rolled by nobody
+    if campaign.mode == CampaignMode::Versus && level.dice().len() != PLAYER_COLOURS.len() {
+        log::error!("Two-player level {} has {} dice but needs exactly {}", current_level.0 + 1, level.dice().len(), PLAYER_COLOURS.len());
+        state.set(GameState::MainMenu);
+        return;
+    }
+
+    // Recursively, so dice take their face numbers with them
+    for entity in old_level_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let teleporter_partners = level.teleporter_partners();
+    let spritesheet = spritesheet.0.clone();
+    for (y, row) in level.tiles.iter().enumerate() {
+        for (x, tile_type) in row.iter().enumerate() {
+            let position = (level.grid_position(x, y) * GRID_SIZE).extend(get_tile_height(tile_type));
 
             let sprite_sheet_bundle = SpriteSheetBundle {
                 texture_atlas: spritesheet.clone(),
@@ -34,47 +138,338 @@ fn setup(
                     ..default()
                 },
                 sprite: TextureAtlasSprite {
-                    index: get_sprite_index(&tile_type),
+                    index: get_sprite_index(tile_type),
                     ..default()
                 },
                 ..default()
             };
             
-            match tile_type {
+            let mut tile = match tile_type {
                 TileType::Floor => commands
                     .spawn((Tile, sprite_sheet_bundle)),
-                TileType::PressurePlate1 => commands
-                    .spawn((Tile, sprite_sheet_bundle, PressurePlate{ activated: false, number: 1})),
-                TileType::PressurePlate2 => commands
-                    .spawn((Tile, sprite_sheet_bundle, PressurePlate{ activated: false, number: 2})),
-                TileType::PressurePlate3 => commands
-                    .spawn((Tile, sprite_sheet_bundle, PressurePlate{ activated: false, number: 3})),
-                TileType::PressurePlate4 => commands
-                    .spawn((Tile, sprite_sheet_bundle, PressurePlate{ activated: false, number: 4})),
-                TileType::PressurePlate5 => commands
-                    .spawn((Tile, sprite_sheet_bundle, PressurePlate{ activated: false, number: 5})),
-                TileType::PressurePlate6 => commands
-                    .spawn((Tile, sprite_sheet_bundle, PressurePlate{ activated: false, number: 6})),
+                TileType::PressurePlate1 |
+                TileType::PressurePlate2 |
+                TileType::PressurePlate3 |
+                TileType::PressurePlate4 |
+                TileType::PressurePlate5 |
+                TileType::PressurePlate6 => {
+                    let number = tile_type.pressure_plate_number().unwrap();
+                    let mode = level.plate_mode(x, y);
+                    let mut plate = commands.spawn((Tile, PressurePlate::new(number, mode, level.plate_order_step(x, y))));
+                    match get_plate_mode_colour(mode) {
+                        Some(colour) => plate.insert((
+                            SpriteSheetBundle {
+                                sprite: TextureAtlasSprite { color: colour, ..sprite_sheet_bundle.sprite },
+                                ..sprite_sheet_bundle
+                            },
+                            TileColour(colour),
+                        )),
+                        None => plate.insert(sprite_sheet_bundle),
+                    };
+                    plate
+                },
                 TileType::Wall => commands
                     .spawn((Tile, sprite_sheet_bundle, Collider)),
+                TileType::Ice => commands
+                    .spawn((Tile, sprite_sheet_bundle, Ice)),
+                TileType::Teleporter(link) => {
+                    let (partner_x, partner_y) = teleporter_partners[&(x, y)];
+                    let colour = get_link_colour(*link as usize - 'a' as usize);
+                    commands.spawn((
+                        Tile,
+                        SpriteSheetBundle {
+                            sprite: TextureAtlasSprite { color: colour, ..sprite_sheet_bundle.sprite },
+                            ..sprite_sheet_bundle
+                        },
+                        TileColour(colour),
+                        Teleporter { partner: level.grid_position(partner_x, partner_y) },
+                    ))
+                },
+                TileType::Conveyor(direction) => {
+                    // The sprite points right, so turn it to face the way the belt goes
+                    let angle = match direction {
+                        Direction::Right => 0.0,
+                        Direction::Up => 90.0,
+                        Direction::Left => 180.0,
+                        Direction::Down => 270.0,
+                    };
+                    let mut sprite_sheet_bundle = sprite_sheet_bundle;
+                    sprite_sheet_bundle.transform.rotation = Quat::from_rotation_z(f32::to_radians(angle));
+                    commands.spawn((Tile, sprite_sheet_bundle, Conveyor(*direction)))
+                },
+                TileType::Rotator { clockwise } => {
+                    // The sprite's arrow goes clockwise, so mirror it for the other way
+                    let mut sprite_sheet_bundle = sprite_sheet_bundle;
+                    sprite_sheet_bundle.sprite.flip_x = !clockwise;
+                    commands.spawn((Tile, sprite_sheet_bundle, Rotator { clockwise: *clockwise }))
+                },
+                TileType::Door { open } => {
+                    let colour = get_link_colour(level.links[&(x, y)]);
+                    let mut door = commands.spawn((
+                        Tile,
+                        SpriteSheetBundle {
+                            sprite: TextureAtlasSprite {
+                                index: get_door_sprite_index(*open),
+                                color: colour,
+                                ..sprite_sheet_bundle.sprite
+                            },
+                            ..sprite_sheet_bundle
+                        },
+                        TileColour(colour),
+                        Door { open_at_start: *open, open: *open },
+                    ));
+                    if !open { door.insert(Collider); }
+                    door
+                },
+                TileType::Switch => {
+                    let colour = get_link_colour(level.links[&(x, y)]);
+                    commands.spawn((
+                        Tile,
+                        SpriteSheetBundle {
+                            sprite: TextureAtlasSprite { color: colour, ..sprite_sheet_bundle.sprite },
+                            ..sprite_sheet_bundle
+                        },
+                        TileColour(colour),
+                        Switch { on: false },
+                    ))
+                },
+                TileType::Pit => commands
+                    .spawn((Tile, sprite_sheet_bundle, Pit)),
+                TileType::Crumbling => commands
+                    .spawn((Tile, sprite_sheet_bundle, Crumbling::default())),
+                TileType::FaceFilter => {
+                    let filter = level.face_filters[&(x, y)];
+                    spawn_tile_label(&mut commands, &asset_server, position, get_face_filter_label(filter), get_face_filter_font_size(filter));
+                    commands.spawn((Tile, sprite_sheet_bundle, Filter(filter)))
+                },
+                TileType::Paint => {
+                    let label = level.paints[&(x, y)];
+                    let font_size = if label == 0 { WORD_LABEL_FONT_SIZE } else { NUMBER_LABEL_FONT_SIZE };
+                    spawn_tile_label(&mut commands, &asset_server, position, get_paint_label(label), font_size);
+                    commands.spawn((Tile, sprite_sheet_bundle, Paint(label)))
+                },
             };
+            if let Some(link) = level.links.get(&(x, y)) {
+                tile.insert(Link(*link));
+            }
         }
     }
+
+    for &(x, y) in level.crates.iter() {
+        let destination = level.grid_position(x, y).extend(CRATE_HEIGHT / GRID_SIZE);
+        commands.spawn((
+            Tile,
+            SpriteSheetBundle {
+                texture_atlas: spritesheet.clone(),
+                transform: Transform {
+                    translation: destination * GRID_SIZE,
+                    scale: Vec3::splat(super::PIXEL_SCALE),
+                    ..default()
+                },
+                sprite: TextureAtlasSprite {
+                    index: CRATE_SPRITE_INDEX,
+                    ..default()
+                },
+                ..default()
+            },
+            Collider,
+            Crate { destination },
+        ));
+    }
+
+    // Restarting keeps the par and solution, or carries on solving the level if it wasn't done yet. Races have no par,
+    // and solving for both dice at once takes far too long on boards big enough for two players.
+    let par = if !level_changed {
+        level_stats.par
+    } else if campaign.mode == CampaignMode::Versus {
+        *level_solution = LevelSolution::default();
+        level_search.running = None;
+        None
+    } else if let Some(par) = campaign.known_par(&current_level) {
+        // Generated levels are only solved again if a hint is asked for
+        *level_solution = LevelSolution::default();
+        level_search.running = None;
+        Some(par)
+    } else if let Some(solution) = level_search.solved.get(level_handle).cloned() {
+        level_search.running = None;
+        let par = solution.par();
+        *level_solution = solution;
+        par
+    } else {
+        // The par shows up once `advance_level_search` has finished solving it
+        *level_solution = LevelSolution::default();
+        level_search.start(level_handle, level);
+        None
+    };
+
+    for (index, die) in level.dice().iter().enumerate() {
+        let (column, row) = die.position;
+        // In two-player mode each die is a player's, as there are always exactly two of them (see above)
+        let player = (campaign.mode == CampaignMode::Versus).then_some(index);
+        spawn_die(&mut commands, &spritesheet, &asset_server.load("fonts/FreeSans.ttf"), level.grid_position(column, row), die, index, player);
+    }
+
+    *level_stats = LevelStats { par, ..default() };
+    *plate_order = PlateOrder {
+        numbers: level.plate_order
+            .iter()
+            .filter_map(|&(column, row)| level.tiles[row][column].pressure_plate_number())
+            .collect(),
+        positions: level.plate_order.iter().map(|&(column, row)| level.grid_position(column, row)).collect(),
+        next: 0,
+    };
+    if !level.plate_order.is_empty() {
+        spawn_next_plate_marker(&mut commands, &asset_server);
+    }
+    spawned_level.0 = Some(level_handle.clone());
+    level_spawned_events.send(LevelSpawned { restarted: restarted && !level_changed });
+}
+
+// Text drawn over a tile, but under the die. It's a `Tile` too so it goes when the level does.
+fn spawn_tile_label(commands: &mut Commands, asset_server: &AssetServer, position: Vec3, label: String, font_size: f32) {
+    commands.spawn((
+        Tile,
+        Text2dBundle {
+            text: Text::from_section(
+                label,
+                TextStyle {
+                    font: asset_server.load("fonts/FreeSans.ttf"),
+                    font_size,
+                    color: TILE_LABEL_COLOUR,
+                },
+            ),
+            transform: Transform::from_translation(position.truncate().extend(0.75)),
+            ..default()
+        },
+    ));
+}
+
+// Points at the next plate in the level's plate order, so it's clear which one it is when plates share a number.
+// `update_next_plate_marker` moves it into place.
+fn spawn_next_plate_marker(commands: &mut Commands, asset_server: &AssetServer) {
+    commands.spawn((
+        Tile,
+        NextPlateMarker,
+        Text2dBundle {
+            text: Text::from_section(
+                "\u{25BC}",
+                TextStyle {
+                    font: asset_server.load("fonts/FreeSans.ttf"),
+                    font_size: NUMBER_LABEL_FONT_SIZE,
+                    color: NEXT_PLATE_COLOUR,
+                },
+            ),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+fn update_next_plate_marker(
+    plate_order: Res<PlateOrder>,
+    mut marker_query: Query<(&mut Transform, &mut Visibility), With<NextPlateMarker>>,
+) {
+    for (mut transform, mut visibility) in marker_query.iter_mut() {
+        let Some(position) = plate_order.next_position() else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        // Just above the plate, so it doesn't hide the number, and drawn over walls in case the tile above is one
+        transform.translation = ((position + Vec2::new(0.0, 0.6)) * GRID_SIZE).extend(1.5);
+        *visibility = Visibility::Visible;
+    }
+}
+
+// Doors are worked out from scratch each time, so undoing a move or restarting puts them back too.
+// Each linked plate that is activated and each linked switch that is on swaps the door over once.
+fn update_doors(
+    mut commands: Commands,
+    pressure_plates_query: Query<(&PressurePlate, &Link)>,
+    switches_query: Query<(&Switch, &Link)>,
+    mut doors_query: Query<(Entity, &mut Door, &Link, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut door, door_link, mut sprite) in doors_query.iter_mut() {
+        let plates_activated = pressure_plates_query
+            .iter()
+            .filter(|(pressure_plate, link)| pressure_plate.activated && link.0 == door_link.0)
+            .count();
+        let switches_on = switches_query
+            .iter()
+            .filter(|(switch, link)| switch.on && link.0 == door_link.0)
+            .count();
+
+        let open = door.open_at_start ^ ((plates_activated + switches_on) % 2 == 1);
+        if open == door.open { continue; }
+
+        door.open = open;
+        sprite.index = get_door_sprite_index(open);
+        if open {
+            commands.entity(entity).remove::<Collider>();
+        }
+        else {
+            commands.entity(entity).insert(Collider);
+        }
+    }
+}
+
+// Crumbled floor is worked out from `Crumbling` each time, so undoing a move or restarting puts it back too
+fn crumble_floor(
+    mut commands: Commands,
+    die_query: Query<&Die>,
+    mut crumbling_query: Query<(Entity, &mut Crumbling, &Transform, &mut TextureAtlasSprite, Option<&Pit>)>,
+) {
+    for (entity, mut crumbling, transform, mut sprite, pit) in crumbling_query.iter_mut() {
+        let die_is_here = die_query.iter().any(|die|
+            (die.destination_translation.truncate() * GRID_SIZE).distance(transform.translation.truncate()) < GRID_SIZE / 2.0);
+        if die_is_here && !crumbling.die_was_here {
+            crumbling.die_was_here = true;
+        }
+        if !die_is_here && crumbling.die_was_here && !crumbling.crumbled {
+            crumbling.crumbled = true;
+        }
+
+        if crumbling.crumbled && pit.is_none() {
+            commands.entity(entity).insert(Pit);
+            sprite.index = PIT_SPRITE_INDEX;
+        }
+        else if !crumbling.crumbled && pit.is_some() {
+            commands.entity(entity).remove::<Pit>();
+            sprite.index = CRUMBLING_SPRITE_INDEX;
+        }
+    }
+}
+
+fn update_switch_appearence(
+    mut switches_query: Query<(&Switch, &mut TextureAtlasSprite), Changed<Switch>>,
+) {
+    for (switch, mut sprite) in switches_query.iter_mut() {
+        sprite.index = get_switch_sprite_index(switch.on);
+    }
 }
 
 fn update_pressure_plate_appearence(
-    mut pressure_plates_query: Query<(& PressurePlate, &mut TextureAtlasSprite)>,
+    mut pressure_plates_query: Query<(& PressurePlate, &mut TextureAtlasSprite, Option<&TileColour>)>,
 ) {
-    for (pressure_plate, mut texture_atlas_sprite) in pressure_plates_query.iter_mut() {
+    for (pressure_plate, mut texture_atlas_sprite, tile_colour) in pressure_plates_query.iter_mut() {
         if pressure_plate.activated {
             texture_atlas_sprite.index = get_pressure_plate_face_sprite_index(pressure_plate.number) - 7;
         }
         else {
             texture_atlas_sprite.index = get_pressure_plate_face_sprite_index(pressure_plate.number);
         }
+        // Plates won in a race take the winner's colour, and lose it again if the plate order starts over.
+        // Other plates are left alone, so a hint showing on one stays put.
+        match pressure_plate.owner {
+            Some(player) => texture_atlas_sprite.color = PLAYER_COLOURS[player],
+            None if PLAYER_COLOURS.contains(&texture_atlas_sprite.color) => {
+                texture_atlas_sprite.color = tile_colour.map_or(Color::WHITE, |tile_colour| tile_colour.0);
+            },
+            None => {},
+        }
     }
 }
 
+#[allow(clippy::needless_return)]
 fn get_pressure_plate_face_sprite_index(face_num: usize) -> usize {
     match face_num {
         1 => return 29,
@@ -87,21 +482,166 @@ fn get_pressure_plate_face_sprite_index(face_num: usize) -> usize {
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
-enum TileType {
-    Wall,
-    Floor,
-    PressurePlate1,
-    PressurePlate2,
-    PressurePlate3,
-    PressurePlate4,
-    PressurePlate5,
-    PressurePlate6,
+#[derive(Component)]
+pub struct Tile;
+
+// The die slides straight across these without rolling
+#[derive(Component)]
+pub struct Ice;
+
+// Landing on one of these moves the die straight to its partner
+#[derive(Component)]
+pub struct Teleporter {
+    pub partner: Vec2, // In grid units
+}
+
+// Landing on one of these rolls the die on again in the belt's direction
+#[derive(Component)]
+pub struct Conveyor(pub Direction);
+
+#[derive(Component)]
+struct NextPlateMarker;
+
+// How far the player has got through the level's plate order
+#[derive(Resource, Default)]
+pub struct PlateOrder {
+    pub numbers: Vec<usize>, // The number on each plate in the order, for showing which comes next
+    pub positions: Vec<Vec2>, // Where each plate in the order is, in grid units
+    pub next: usize, // Index of the next plate to activate
+}
+
+impl PlateOrder {
+    pub fn next_number(&self) -> Option<usize> {
+        self.numbers.get(self.next).copied()
+    }
+
+    pub fn next_position(&self) -> Option<Vec2> {
+        self.positions.get(self.next).copied()
+    }
+}
+
+// The die can only roll onto one of these with a number the filter lets through face up
+#[derive(Component)]
+pub struct Filter(pub FaceFilter);
+
+// Pushed along by the die rolling into it. It holds down any pressure plate it's on.
+#[derive(Component)]
+pub struct Crate {
+    pub destination: Vec3, // In grid units, like `Die::destination_translation`
+}
+
+// Landing on one of these relabels the side of the die against it
+#[derive(Component)]
+pub struct Paint(pub usize);
+
+// Landing on one of these spins the die a quarter turn where it stands
+#[derive(Component)]
+pub struct Rotator {
+    pub clockwise: bool,
+}
+
+// The die falls in if it lands on one of these, and the level has to be restarted
+#[derive(Component)]
+pub struct Pit;
+
+// Floor that can only be crossed once, falling away into a pit after the die leaves it
+#[derive(Component, Clone, Copy, Default)]
+pub struct Crumbling {
+    pub die_was_here: bool,
+    pub crumbled: bool,
+}
+
+// Tint for tiles that aren't drawn in the spritesheet's own colours, e.g. to tell teleporter pairs apart
+#[derive(Component)]
+pub struct TileColour(pub Color);
+
+pub const TELEPORTER_SPRITE_INDEX: usize = 39;
+const PIT_SPRITE_INDEX: usize = 62;
+const CRUMBLING_SPRITE_INDEX: usize = 35;
+const CRATE_SPRITE_INDEX: usize = 69;
+const CRATE_HEIGHT: f32 = 0.9; // Above the tiles and their labels, but under the die
+// Doors and switches (and any plates) with the same link ID are connected
+#[derive(Component)]
+pub struct Link(pub usize);
+
+#[derive(Component)]
+pub struct Door {
+    pub open_at_start: bool,
+    pub open: bool,
 }
 
+// Landing on a switch flicks it on or off, whichever way up the die is
 #[derive(Component)]
-struct Tile;
+pub struct Switch {
+    pub on: bool,
+}
+
+// Used to tell apart teleporter pairs and groups of linked tiles
+const LINK_COLOURS: [Color; 6] = [
+    Color::rgb(0.55, 0.75, 1.0),
+    Color::rgb(1.0, 0.6, 0.85),
+    Color::rgb(0.6, 1.0, 0.6),
+    Color::rgb(1.0, 0.8, 0.45),
+    Color::rgb(0.8, 0.6, 1.0),
+    Color::rgb(0.5, 1.0, 0.95),
+];
+
+fn get_link_colour(link: usize) -> Color {
+    LINK_COLOURS[link % LINK_COLOURS.len()]
+}
+
+// Plates that let go when the die leaves are tinted, so they can be told apart from ordinary ones
+#[allow(clippy::needless_return)]
+fn get_plate_mode_colour(mode: PlateMode) -> Option<Color> {
+    match mode {
+        PlateMode::Latching => return None,
+        PlateMode::Momentary => return Some(Color::rgb(0.7, 0.85, 1.0)),
+        PlateMode::Held => return Some(Color::rgb(1.0, 0.8, 0.55)),
+    }
+}
+
+const TILE_LABEL_COLOUR: Color = Color::rgb(0.25, 0.15, 0.3);
+const NEXT_PLATE_COLOUR: Color = Color::rgb(1.0, 0.6, 0.1);
+const NUMBER_LABEL_FONT_SIZE: f32 = 28.0;
+const WORD_LABEL_FONT_SIZE: f32 = 16.0; // Words need to be smaller than a number to fit on the tile
+
+#[allow(clippy::needless_return)]
+fn get_face_filter_label(filter: FaceFilter) -> String {
+    match filter {
+        FaceFilter::Number(number) => return number.to_string(),
+        FaceFilter::Odd => return "odd".to_string(),
+        FaceFilter::Even => return "even".to_string(),
+    }
+}
+
+// Blank faces can be painted on too
+#[allow(clippy::needless_return)]
+fn get_paint_label(label: usize) -> String {
+    if label == 0 { return "blank".to_string(); }
+    return label.to_string();
+}
+
+#[allow(clippy::needless_return)]
+fn get_face_filter_font_size(filter: FaceFilter) -> f32 {
+    match filter {
+        FaceFilter::Number(_) => return NUMBER_LABEL_FONT_SIZE,
+        FaceFilter::Odd | FaceFilter::Even => return WORD_LABEL_FONT_SIZE,
+    }
+}
+
+#[allow(clippy::needless_return)]
+fn get_door_sprite_index(open: bool) -> usize {
+    if open { return 55; }
+    return 48;
+}
+
+#[allow(clippy::needless_return)]
+fn get_switch_sprite_index(on: bool) -> usize {
+    if on { return 61; }
+    return 60;
+}
 
+#[allow(clippy::needless_return)]
 fn get_sprite_index(tile_type: &TileType) -> usize {
     match tile_type {
         TileType::Floor => return 28,
@@ -112,12 +652,33 @@ fn get_sprite_index(tile_type: &TileType) -> usize {
         TileType::PressurePlate5 => return 33,
         TileType::PressurePlate6 => return 34,
         TileType::Wall => return 47,
+        TileType::Ice => return 38,
+        TileType::Teleporter(_) => return TELEPORTER_SPRITE_INDEX,
+        TileType::Conveyor(_) => return 40,
+        TileType::Rotator { .. } => return 41,
+        TileType::Door { open } => return get_door_sprite_index(*open),
+        TileType::Switch => return get_switch_sprite_index(false),
+        TileType::Pit => return PIT_SPRITE_INDEX,
+        TileType::Crumbling => return CRUMBLING_SPRITE_INDEX,
+        TileType::FaceFilter => return 67,
+        TileType::Paint => return 68,
     }
 }
 
+#[allow(clippy::needless_return)]
 fn get_tile_height(tile_type: &TileType) -> f32 {
     match tile_type {
-        TileType::Floor => return 0.5,
+        TileType::Floor |
+        TileType::Ice |
+        TileType::Teleporter(_) |
+        TileType::Conveyor(_) |
+        TileType::Rotator { .. } |
+        TileType::Door { .. } |
+        TileType::Switch |
+        TileType::Pit |
+        TileType::Crumbling |
+        TileType::FaceFilter |
+        TileType::Paint => return 0.5,
         TileType::PressurePlate1 | 
         TileType::PressurePlate2 | 
         TileType::PressurePlate3 | 
@@ -128,22 +689,260 @@ fn get_tile_height(tile_type: &TileType) -> f32 {
     }
 }
 
+// The campaign, in the order it is played
+const CAMPAIGN_LEVELS: [&str; 3] = [
+    "levels/level_01.level.ron",
+    "levels/level_02.level.ron",
+    "levels/level_03.level.ron",
+];
+
+#[derive(Resource, Clone)]
+pub struct StoryLevels(Vec<Handle<Level>>);
+
+// Boards for two-player mode, each with a die for both players
+const VERSUS_LEVELS: [&str; 2] = [
+    "levels/versus_01.level.ron",
+    "levels/versus_02.level.ron",
+];
+
+#[derive(Resource, Clone)]
+pub struct VersusLevels(Vec<Handle<Level>>);
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum CampaignMode {
+    Story,
+    Endless { seed: u64 }, // Generates another level from the seed whenever the last one is beaten
+    Daily { date: Date },
+    Versus, // Two players race each other to the plates
+}
+
+// The levels being played through
+#[derive(Resource)]
+pub struct Campaign {
+    levels: Vec<Handle<Level>>,
+    pars: Vec<Option<usize>>, // Known up front for generated levels, which were solved while generating them
+    pub mode: CampaignMode,
+}
+
+impl Campaign {
+    pub fn story(story_levels: &StoryLevels) -> Campaign {
+        Campaign {
+            levels: story_levels.0.clone(),
+            pars: vec![None; story_levels.0.len()],
+            mode: CampaignMode::Story,
+        }
+    }
+
+    #[allow(clippy::needless_return)]
+    pub fn endless(seed: u64, levels: &mut Assets<Level>) -> Campaign {
+        let mut campaign = Campaign {
+            levels: Vec::new(),
+            pars: Vec::new(),
+            mode: CampaignMode::Endless { seed },
+        };
+        campaign.generate_endless_level(levels);
+        return campaign;
+    }
+
+    // A single generated level that is the same for everyone playing on `date`
+    pub fn daily(date: Date, levels: &mut Assets<Level>) -> Campaign {
+        let generated = generator::generate_easing_off(date.seed(), generator::daily_settings());
+        Campaign {
+            levels: vec![levels.add(generated.level)],
+            pars: vec![Some(generated.par)],
+            mode: CampaignMode::Daily { date },
+        }
+    }
+
+    pub fn versus(versus_levels: &VersusLevels) -> Campaign {
+        Campaign {
+            levels: versus_levels.0.clone(),
+            pars: vec![None; versus_levels.0.len()],
+            mode: CampaignMode::Versus,
+        }
+    }
+
+    pub fn handle(&self, current_level: &CurrentLevel) -> &Handle<Level> {
+        &self.levels[current_level.0]
+    }
+
+    pub fn level<'a>(&self, current_level: &CurrentLevel, levels: &'a Assets<Level>) -> Option<&'a Level> {
+        levels.get(self.handle(current_level))
+    }
+
+    // The par of a generated level, which doesn't need solving again to find it
+    pub fn known_par(&self, current_level: &CurrentLevel) -> Option<usize> {
+        self.pars[current_level.0]
+    }
+
+    pub fn is_last(&self, current_level: &CurrentLevel) -> bool {
+        !matches!(self.mode, CampaignMode::Endless { .. }) && current_level.0 + 1 >= self.levels.len()
+    }
+
+    pub fn advance(&mut self, current_level: &mut CurrentLevel, levels: &mut Assets<Level>) {
+        if current_level.0 + 1 >= self.levels.len() {
+            self.generate_endless_level(levels);
+        }
+        current_level.0 += 1;
+    }
+
+    fn generate_endless_level(&mut self, levels: &mut Assets<Level>) {
+        let CampaignMode::Endless { seed } = self.mode else { return; };
+        let level_index = self.levels.len();
+
+        // Each level has its own seed, so a run can be replayed from the first one
+        let level_seed = seed.wrapping_add(level_index as u64);
+        let generated = generator::generate_easing_off(level_seed, generator::endless_settings(level_index));
+        self.levels.push(levels.add(generated.level));
+        self.pars.push(Some(generated.par));
+    }
+}
+
+// How the player is getting on with the current level
+#[derive(Resource, Default)]
+pub struct LevelStats {
+    pub moves: usize,
+    pub time: Stopwatch,
+    pub par: Option<usize>, // Fewest moves the level can be solved in
+}
+
+impl LevelStats {
+    // Out of 3: matching par gets all 3, within half as many moves again gets 2
+    #[allow(clippy::needless_return)]
+    pub fn stars(&self) -> Option<usize> {
+        let par = self.par?;
+        if self.moves <= par { return Some(3); }
+        if self.moves * 2 <= par * 3 { return Some(2); }
+        return Some(1);
+    }
+}
+
+// The shortest solution to the current level, worked out once by `LevelSearch` so hints can follow it.
+// Asking for a hint after straying from it replaces it with a solution from there.
+#[derive(Resource, Default, Clone)]
+pub struct LevelSolution {
+    pub rolls: Option<Vec<Roll>>, // None if the level can't be solved, or it wasn't worked out (generated levels and races)
+    pub states: Vec<PuzzleState>, // The state before each roll, see `Puzzle::canonical`
+}
+
+impl LevelSolution {
+    pub fn new(puzzle: &Puzzle, start: PuzzleState, rolls: Option<Vec<Roll>>) -> LevelSolution {
+        let states = rolls
+            .as_ref()
+            .and_then(|rolls| puzzle.states_along(start, rolls))
+            .unwrap_or_default();
+        LevelSolution {
+            rolls,
+            states: states.iter().map(Puzzle::canonical).collect(),
+        }
+    }
+
+    pub fn par(&self) -> Option<usize> {
+        self.rolls.as_ref().map(|rolls| rolls.len())
+    }
+}
+
+// Levels are solved this many states a frame, a few milliseconds' worth in a release build
+const SOLVE_STATES_PER_FRAME: usize = 2000;
+
+// Solving the current level a bit at a time, so big levels don't freeze the game while they're solved
+#[derive(Resource, Default)]
+pub struct LevelSearch {
+    running: Option<RunningSearch>,
+    solved: HashMap<Handle<Level>, LevelSolution>, // So levels played again don't have to be solved again
+}
+
+struct RunningSearch {
+    level: Handle<Level>,
+    puzzle: Puzzle,
+    start: PuzzleState,
+    search: Search,
+}
+
+impl LevelSearch {
+    fn start(&mut self, level_handle: &Handle<Level>, level: &Level) {
+        let puzzle = Puzzle::new(level);
+        let start = puzzle.start_state();
+        self.running = Some(RunningSearch { level: level_handle.clone(), puzzle, start, search: Search::new(start) });
+    }
+
+    pub fn is_solving(&self) -> bool {
+        self.running.is_some()
+    }
+}
+
+// Once the level is solved, the par and the solution for hints are filled in
+fn advance_level_search(
+    current_level: Res<CurrentLevel>,
+    mut level_search: ResMut<LevelSearch>,
+    mut level_solution: ResMut<LevelSolution>,
+    mut level_stats: ResMut<LevelStats>,
+) {
+    let Some(mut running) = level_search.running.take() else { return; };
+    let rolls = match running.search.advance(&running.puzzle, SOLVE_STATES_PER_FRAME) {
+        SearchProgress::Searching => {
+            level_search.running = Some(running);
+            return;
+        },
+        SearchProgress::Solved(rolls) => Some(rolls),
+        SearchProgress::Unsolvable => None,
+    };
+
+    let solution = LevelSolution::new(&running.puzzle, running.start, rolls);
+    match solution.par() {
+        Some(par) => log::info!("Level {} can be solved in {} moves", current_level.0 + 1, par),
+        None => log::warn!("Level {} cannot be solved", current_level.0 + 1),
+    }
+    level_stats.par = solution.par();
+    *level_solution = solution.clone();
+    level_search.solved.insert(running.level, solution);
+}
+
+// Sent once the tiles for the current level have been spawned and the die placed on its start
+#[derive(Event)]
+pub struct LevelSpawned {
+    pub restarted: bool, // The player restarted the level they were already on
+}
+
+// Put the current level back how it started
+#[derive(Event)]
+pub struct RestartLevel;
+
+// Index into the campaign of the level being played
+#[derive(Resource, Default)]
+pub struct CurrentLevel(pub usize);
+
+// The level whose tiles are out, which lags behind `CurrentLevel` while the next one loads
+#[derive(Resource, Default)]
+pub struct SpawnedLevel(pub Option<Handle<Level>>);
+
+// Levels are framed for a board of this many tiles across
 pub const WORLD_SIZE: usize = 14;
-const WORLD_CENTRE: usize = WORLD_SIZE / 2;
-
-const WORLD: [[TileType; WORLD_SIZE]; WORLD_SIZE] = [
-    [TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall],
-    [TileType::Wall, TileType::PressurePlate1, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::PressurePlate6, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::PressurePlate2, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::PressurePlate5, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall, TileType::Floor, TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::PressurePlate3, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall, TileType::Floor, TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall, TileType::PressurePlate2, TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall],
-    [TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Wall, TileType::Floor, TileType::Wall, TileType::Floor, TileType::Floor, TileType::Floor, TileType::Floor, TileType::PressurePlate4, TileType::Wall],
-    [TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall, TileType::Wall],
-];
\ No newline at end of file
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stars(moves: usize, par: Option<usize>) -> Option<usize> {
+        LevelStats { moves, par, ..default() }.stars()
+    }
+
+    #[test]
+    fn matching_par_gets_three_stars() {
+        assert_eq!(stars(8, Some(8)), Some(3));
+        assert_eq!(stars(5, Some(8)), Some(3));
+    }
+
+    #[test]
+    fn up_to_half_as_many_moves_again_gets_two_stars() {
+        assert_eq!(stars(9, Some(8)), Some(2));
+        assert_eq!(stars(12, Some(8)), Some(2));
+        assert_eq!(stars(13, Some(8)), Some(1));
+        assert_eq!(stars(100, Some(8)), Some(1));
+    }
+
+    #[test]
+    fn no_par_means_no_stars() {
+        assert_eq!(stars(3, None), None);
+    }
+}